@@ -0,0 +1,48 @@
+// Chunk material: the standard PBR material, with each quad's texture coordinates wrapped into its block's tile.
+// UV 0 counts blocks across the quad and UV 1 holds the corner of the block's tile in the sheet,
+// so one tile repeats across every block a greedy quad covers.
+#import bevy_pbr::{
+    pbr_fragment::pbr_input_from_standard_material,
+    pbr_functions::alpha_discard,
+}
+
+#ifdef PREPASS_PIPELINE
+#import bevy_pbr::{
+    prepass_io::{VertexOutput, FragmentOutput},
+    pbr_deferred_functions::deferred_output,
+}
+#else
+#import bevy_pbr::{
+    forward_io::{VertexOutput, FragmentOutput},
+    pbr_functions::{apply_pbr_lighting, main_pass_post_lighting_processing},
+}
+#endif
+
+struct AtlasTiling {
+    tile_size: vec2<f32>,
+}
+
+@group(2) @binding(100)
+var<uniform> atlas_tiling: AtlasTiling;
+
+@fragment
+fn fragment(
+    in: VertexOutput,
+    @builtin(front_facing) is_front: bool,
+) -> FragmentOutput {
+    var tiled = in;
+#ifdef VERTEX_UVS_B
+    tiled.uv = in.uv_b + fract(in.uv) * atlas_tiling.tile_size;
+#endif
+    var pbr_input = pbr_input_from_standard_material(tiled, is_front);
+    pbr_input.material.base_color = alpha_discard(pbr_input.material, pbr_input.material.base_color);
+
+#ifdef PREPASS_PIPELINE
+    let out = deferred_output(in, pbr_input);
+#else
+    var out: FragmentOutput;
+    out.color = apply_pbr_lighting(pbr_input);
+    out.color = main_pass_post_lighting_processing(pbr_input, out.color);
+#endif
+    return out;
+}
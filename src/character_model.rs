@@ -5,6 +5,12 @@ use bevy::{
 };
 
 use crate::animation::CharacterAnimation;
+use crate::voxel_terrain::VoxLoaderSettings;
+
+// MagicaVoxel models for the character and its hat, and the world size of one of their voxels
+pub const CHARACTER_MODEL: &str = "models/character.vox";
+pub const HAT_MODEL: &str = "models/hat.vox";
+pub const MODEL_VOXEL_SIZE: f32 = 1.0 / 12.0;
 
 // Define a struct for our character
 #[derive(Component)]
@@ -40,26 +46,31 @@ impl Plugin for CharacterPlugin {
     }
 }
 
+// Load a MagicaVoxel model at the size characters are drawn at; the asset server loads each file only once
+pub fn load_model(asset_server: &AssetServer, path: &'static str) -> Handle<Mesh> {
+    asset_server.load_with_settings(path, |settings: &mut VoxLoaderSettings| settings.voxel_size = MODEL_VOXEL_SIZE)
+}
+
 // System to set up character assets
 fn setup_characters(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
+    asset_server: Res<AssetServer>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     mut character_assets: ResMut<CharacterAssets>,
 ) {
-    // Create a cuboid mesh for the character body
-    character_assets.character_mesh = meshes.add(Mesh::from(shape::Cube { size: 1.0 }));
+    // Load the character body model; its colours come from its palette as vertex colours
+    character_assets.character_mesh = load_model(&asset_server, CHARACTER_MODEL);
     // Create a material for the character
     character_assets.character_material = materials.add(StandardMaterial {
-        base_color: Color::rgb(0.8, 0.7, 0.6),
+        base_color: Color::WHITE,
         ..Default::default()
     });
 
-    // Create a smaller cuboid mesh for the hat
-    character_assets.hat_mesh = meshes.add(Mesh::from(shape::Cube { size: 0.5 }));
+    // Load the hat model
+    character_assets.hat_mesh = load_model(&asset_server, HAT_MODEL);
     // Create a material for the hat
     character_assets.hat_material = materials.add(StandardMaterial {
-        base_color: Color::rgb(0.1, 0.1, 0.1),
+        base_color: Color::WHITE,
         ..Default::default()
     });
 
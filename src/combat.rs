@@ -2,13 +2,114 @@ use bevy::prelude::*;
 use bevy::math::Vec3;
 use rand::Rng; // Assuming rand is in the dependencies
 
+use crate::voxel_terrain::{BlockLanded, BlockRegistry, VoxelTerrain};
+
+mod damage;
+pub use damage::{Damage, DamageFormula, DamageFormulaError, DamageType, Hit, Resistances};
+
+mod status;
+pub use status::{apply_status_system, ApplyStatus, Stacking, StatusEffect, StatusEffects, StatusKind, StatusTick};
+
+mod stats;
+pub use stats::{stats_system, BaseStats, Buffs, Modifier, Stat, StatModifier, Stats};
+
 // Define components for combat-related properties
-#[derive(Component)]
-pub struct Health(pub u32);
-#[derive(Component)]
-pub struct Attack(pub u32);
-#[derive(Component)]
-pub struct Defense(pub u32);
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Health {
+    pub current: u32,
+    pub max: u32,
+}
+
+impl Health {
+    // Full health with the given maximum
+    pub fn new(max: u32) -> Self {
+        Health { current: max, max }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current == 0
+    }
+
+    // Restore health without going over the maximum, returning how much was restored
+    pub fn heal(&mut self, amount: u32) -> u32 {
+        let healed = amount.min(self.max.saturating_sub(self.current));
+        self.current += healed;
+        healed
+    }
+}
+
+// Marks an entity whose health ran out; it lies as a corpse until it is despawned or respawned
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct Dead {
+    // Seconds until the corpse is removed or the entity respawns
+    pub remaining: f32,
+}
+
+// Where an entity comes back to life after dying, instead of being despawned
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct Checkpoint(pub Vec3);
+
+// How long the dead stay dead
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct DeathSettings {
+    // Seconds a corpse lies before it is despawned along with its children
+    pub corpse_duration: f32,
+    // Seconds before an entity with a checkpoint comes back to life there
+    pub respawn_delay: f32,
+}
+
+impl Default for DeathSettings {
+    fn default() -> Self {
+        DeathSettings {
+            corpse_duration: 10.0,
+            respawn_delay: 3.0,
+        }
+    }
+}
+
+// Damage an entity's attacks deal before the target's defenses, and its chance to land a critical hit
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct Attack {
+    pub damage: Damage,
+    // Chance in 0..1 that a hit is critical
+    pub crit_chance: f32,
+    // Factor critical hits multiply every type of damage by
+    pub crit_multiplier: f32,
+}
+
+impl Attack {
+    // An attack dealing only physical damage, with the default crit chance
+    pub fn physical(amount: f32) -> Self {
+        Attack {
+            damage: Damage::of(DamageType::Physical, amount),
+            ..default()
+        }
+    }
+}
+
+impl Default for Attack {
+    fn default() -> Self {
+        Attack {
+            damage: Damage::default(),
+            crit_chance: 0.05,
+            crit_multiplier: 1.5,
+        }
+    }
+}
+
+// Armor and per-type resistances that reduce the damage an entity takes
+#[derive(Component, Debug, Clone, Copy, PartialEq, Default)]
+pub struct Defense {
+    // Mitigates the damage types the damage formula lists for armor, with diminishing returns
+    pub armor: f32,
+    pub resistances: Resistances,
+}
+
+impl Defense {
+    pub fn armor(armor: f32) -> Self {
+        Defense { armor, ..default() }
+    }
+}
 
 // Additional components for AI
 #[derive(Component)]
@@ -18,12 +119,75 @@ pub struct Player;
 #[derive(Component)]
 pub struct Velocity(pub Vec3);
 
-// Components for item effects
-#[derive(Component)]
-pub struct ItemEffects {
-    pub health_bonus: i32,
-    pub attack_bonus: i32,
-    pub defense_bonus: i32,
+// Seconds an entity must wait between attacks; attack intents sent while it is cooling down are ignored
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct AttackCooldown {
+    pub duration: f32,
+    pub remaining: f32,
+}
+
+impl AttackCooldown {
+    pub fn new(duration: f32) -> Self {
+        AttackCooldown { duration, remaining: 0.0 }
+    }
+}
+
+// Effects an entity's attacks put on everything they hit
+#[derive(Component, Debug, Clone, Default, PartialEq)]
+pub struct OnHitEffects(pub Vec<StatusEffect>);
+
+// What an attack is aimed at
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AttackTarget {
+    Entity(Entity),
+    // Every entity with health within the radius of the point, except the attacker
+    Area { center: Vec3, radius: f32 },
+}
+
+// Event sent when an entity tries to attack; damage is worked out when the combat plugin resolves it
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct AttackIntent {
+    pub attacker: Entity,
+    pub target: AttackTarget,
+}
+
+// Event sent for every hit on an entity, by an attacker or by the environment
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct DamageDealt {
+    // None for damage from the terrain, such as lava or falling blocks
+    pub attacker: Option<Entity>,
+    pub target: Entity,
+    // Health taken away
+    pub amount: u32,
+    // Damage of each type that got through the target's defenses
+    pub damage: Damage,
+    pub critical: bool,
+}
+
+impl DamageDealt {
+    fn new(attacker: Option<Entity>, target: Entity, hit: Hit) -> Self {
+        DamageDealt {
+            attacker,
+            target,
+            amount: hit.amount,
+            damage: hit.damage,
+            critical: hit.critical,
+        }
+    }
+}
+
+// Event sent once when an entity's health drops to zero
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntityDied {
+    pub entity: Entity,
+    // Entity that dealt the killing blow; None when the terrain did
+    pub killer: Option<Entity>,
+}
+
+// Event sent when a dead entity comes back to life at its checkpoint
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntityRespawned {
+    pub entity: Entity,
 }
 
 // Plugin to set up combat systems
@@ -32,74 +196,352 @@ pub struct CombatPlugin;
 impl Plugin for CombatPlugin {
     fn build(&self, app: &mut App) {
         app
-            .add_systems(attack_system)
-            .add_systems(health_system)
-            .add_systems(enemy_ai_system);
+            .init_resource::<DamageFormula>()
+            .init_resource::<DeathSettings>()
+            .add_event::<AttackIntent>()
+            .add_event::<DamageDealt>()
+            .add_event::<EntityDied>()
+            .add_event::<EntityRespawned>()
+            .add_event::<ApplyStatus>()
+            // Attacks flow from intent to resolved damage to death within the same frame
+            .add_systems(
+                Update,
+                (
+                    enemy_ai_system,
+                    cooldown_system,
+                    stats_system,
+                    regeneration_stat_system,
+                    resolve_attacks_system,
+                    apply_status_system,
+                    health_system,
+                    apply_damage_system,
+                    death_system,
+                    corpse_system,
+                )
+                    .chain(),
+            )
+            .add_systems(FixedUpdate, block_damage_system)
+            .add_systems(Update, falling_block_damage_system);
     }
 }
 
-// System to handle attacks
-fn attack_system(
+// System to count down attack cooldowns
+fn cooldown_system(time: Res<Time>, mut query: Query<&mut AttackCooldown>) {
+    for mut cooldown in query.iter_mut() {
+        cooldown.remaining = (cooldown.remaining - time.delta_seconds()).max(0.0);
+    }
+}
+
+// Components that change how an entity attacks or is hit, any of which it may lack
+type CombatantData = (
+    Option<&'static Defense>,
+    Option<&'static StatusEffects>,
+    Option<&'static OnHitEffects>,
+    Has<Dead>,
+);
+
+// Query filter for entities that have health and are still alive
+type Living = (With<Health>, Without<Dead>);
+
+// System to turn attack intents into damage against their targets
+fn resolve_attacks_system(
+    formula: Res<DamageFormula>,
+    mut intents: EventReader<AttackIntent>,
+    mut damage: EventWriter<DamageDealt>,
+    mut statuses: EventWriter<ApplyStatus>,
+    mut attackers: Query<(&Attack, Option<&mut AttackCooldown>)>,
+    targets: Query<(Entity, &Transform), Living>,
+    combatants: Query<CombatantData>,
+) {
+    for intent in intents.read() {
+        // Only living entities that can attack, are not stunned and are not cooling down get to hit anything
+        let Ok((attack, cooldown)) = attackers.get_mut(intent.attacker) else {
+            continue;
+        };
+        let (_, attacker_status, on_hit, attacker_dead) = combatants.get(intent.attacker).unwrap_or_default();
+        if attacker_dead || attacker_status.is_some_and(StatusEffects::is_stunned) {
+            continue;
+        }
+        if let Some(mut cooldown) = cooldown {
+            if cooldown.remaining > 0.0 {
+                continue;
+            }
+            cooldown.remaining = cooldown.duration;
+        }
+        let mut rng = rand::thread_rng();
+        let mut hit = |target: Entity| {
+            let (defense, _, _, _) = combatants.get(target).unwrap_or_default();
+            let defense = defense.copied().unwrap_or_default();
+            DamageDealt::new(Some(intent.attacker), target, formula.resolve_attack(attack, &defense, rng.gen()))
+        };
+        let hit_targets: Vec<Entity> = match intent.target {
+            AttackTarget::Entity(target) => (target != intent.attacker && targets.contains(target)).then_some(target).into_iter().collect(),
+            AttackTarget::Area { center, radius } => targets
+                .iter()
+                .filter(|(target, transform)| *target != intent.attacker && transform.translation.distance(center) <= radius)
+                .map(|(target, _)| target)
+                .collect(),
+        };
+        for target in hit_targets {
+            damage.send(hit(target));
+            if let Some(on_hit) = on_hit {
+                statuses.send_batch(on_hit.0.iter().map(|effect| ApplyStatus {
+                    target,
+                    effect: effect.from_source(intent.attacker),
+                }));
+            }
+        }
+    }
+}
+
+// System to take damage from health, announcing deaths
+fn apply_damage_system(
+    mut damage: EventReader<DamageDealt>,
+    mut died: EventWriter<EntityDied>,
+    mut query: Query<(&mut Health, Option<&mut StatusEffects>)>,
+) {
+    for event in damage.read() {
+        let Ok((mut health, effects)) = query.get_mut(event.target) else {
+            continue;
+        };
+        // Entities already at zero health died on an earlier hit
+        if health.is_dead() || event.amount == 0 {
+            continue;
+        }
+        // Shields soak up damage before it reaches health
+        let amount = effects.map_or(event.amount, |mut effects| effects.absorb(event.amount));
+        if amount == 0 {
+            continue;
+        }
+        health.current = health.current.saturating_sub(amount);
+        debug!("Entity {:?} takes {} damage, health is now {}/{}", event.target, amount, health.current, health.max);
+        if health.is_dead() {
+            died.send(EntityDied {
+                entity: event.target,
+                killer: event.attacker,
+            });
+        }
+    }
+}
+
+// System to lay entities that have died down as corpses
+fn death_system(
+    mut commands: Commands,
+    settings: Res<DeathSettings>,
+    mut died: EventReader<EntityDied>,
+    mut query: Query<(Option<&mut Velocity>, Has<Checkpoint>)>,
+) {
+    for event in died.read() {
+        let Ok((velocity, has_checkpoint)) = query.get_mut(event.entity) else {
+            continue;
+        };
+        // Corpses stop walking but still fall
+        if let Some(mut velocity) = velocity {
+            velocity.0.x = 0.0;
+            velocity.0.z = 0.0;
+        }
+        let remaining = if has_checkpoint { settings.respawn_delay } else { settings.corpse_duration };
+        commands.entity(event.entity).insert(Dead { remaining });
+        debug!("Entity {:?} has been defeated by {:?}", event.entity, event.killer);
+    }
+}
+
+// System to remove corpses once they have lain long enough, bringing entities with a checkpoint back to life there
+fn corpse_system(
     mut commands: Commands,
-    _time: Res<Time>,
-    mut query: Query<(Entity, &Attack, &mut Health, &Defense, Option<&ItemEffects>)>,
+    time: Res<Time>,
+    mut respawned: EventWriter<EntityRespawned>,
+    mut corpses: Query<(Entity, &mut Dead, &mut Health, &mut Transform, Option<&Checkpoint>)>,
+    mut status_effects: Query<&mut StatusEffects>,
 ) {
-    for (entity, attack, mut health, defense, item_effects) in query.iter_mut() {
-        // Calculate total attack power with item effects
-        let total_attack = attack.0 + item_effects.map_or(0, |effects| effects.attack_bonus as u32);
+    for (entity, mut dead, mut health, mut transform, checkpoint) in corpses.iter_mut() {
+        dead.remaining -= time.delta_seconds();
+        if dead.remaining > 0.0 {
+            continue;
+        }
+        let Some(checkpoint) = checkpoint else {
+            // Children such as hats and held items go with the corpse
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
 
-        // Simple attack logic: if total attack power is greater than defense, reduce health
-        if total_attack > defense.0 {
-            health.0 = health.0.saturating_sub(total_attack - defense.0);
-            println!("Entity {:?} attacks with power {}, health is now {}", entity, total_attack, health.0);
+        health.current = health.max;
+        transform.translation = checkpoint.0;
+        // Burns and poisons do not follow the entity back, but lasting effects from its items do
+        if let Ok(mut effects) = status_effects.get_mut(entity) {
+            effects.effects.retain(|effect| effect.remaining.is_infinite());
         }
+        commands.entity(entity).remove::<Dead>();
+        respawned.send(EntityRespawned { entity });
+        debug!("Entity {:?} respawns at {:?}", entity, checkpoint.0);
     }
 }
 
-// System to handle health updates
+// Seconds burning lasts after leaving a damaging block
+const BURNING_DURATION: f32 = 3.0;
+
+// System to keep the regeneration stat as a lasting regeneration effect, healing that much health each second
+fn regeneration_stat_system(
+    query: Query<(Entity, &Stats, Option<&StatusEffects>), Changed<Stats>>,
+    mut statuses: EventWriter<ApplyStatus>,
+) {
+    for (entity, stats, effects) in query.iter() {
+        // Reapplying the effect restarts its tick timer, so leave it alone unless the bonus changed
+        let current = effects
+            .and_then(|effects| effects.get(StatusKind::Regeneration).find(|effect| effect.source == Some(entity)))
+            .map_or(0.0, |effect| effect.magnitude);
+        if current == stats.regeneration {
+            continue;
+        }
+        // Clear the old regeneration first so a smaller bonus replaces a larger one
+        let regeneration = StatusEffect::new(StatusKind::Regeneration, stats.regeneration, 0.0).from_source(entity);
+        statuses.send(ApplyStatus { target: entity, effect: regeneration });
+        if stats.regeneration > 0.0 {
+            statuses.send(ApplyStatus {
+                target: entity,
+                effect: StatusEffect {
+                    remaining: f32::INFINITY,
+                    ..regeneration
+                },
+            });
+        }
+    }
+}
+
+// System to handle health updates from status effects: damage over time and regeneration
 fn health_system(
-    mut commands: Commands,
-    _time: Res<Time>,
-    mut query: Query<(Entity, &mut Health, Option<&ItemEffects>)>,
+    time: Res<Time>,
+    formula: Res<DamageFormula>,
+    mut damage_dealt: EventWriter<DamageDealt>,
+    mut query: Query<(Entity, &mut Health, &mut StatusEffects, Option<&Defense>), Without<Dead>>,
 ) {
-    for (entity, mut health, item_effects) in query.iter_mut() {
-        // Apply health bonus from item effects
-        let health_bonus = item_effects.map_or(0, |effects| effects.health_bonus as u32);
-        health.0 += health_bonus;
+    for (entity, mut health, mut effects, defense) in query.iter_mut() {
+        for tick in effects.tick(time.delta_seconds()) {
+            let damage_type = match tick.kind {
+                StatusKind::Burning => DamageType::Fire,
+                StatusKind::Poison => DamageType::Poison,
+                StatusKind::Regeneration => {
+                    health.heal(tick.amount.round().max(0.0) as u32);
+                    continue;
+                }
+                _ => continue,
+            };
+            let hit = formula.resolve_damage(Damage::of(damage_type, tick.amount), &defense.copied().unwrap_or_default());
+            damage_dealt.send(DamageDealt::new(tick.source, entity, hit));
+        }
+    }
+}
 
-        // Check if the entity is out of health and remove it
-        if health.0 == 0 {
-            commands.entity(entity).despawn();
-            println!("Entity {:?} has been defeated", entity);
+// System to set entities standing in damaging blocks such as lava on fire, burning once per second for that damage
+fn block_damage_system(
+    time: Res<Time>,
+    mut elapsed: Local<f32>,
+    terrain: Option<Res<VoxelTerrain>>,
+    registry: Option<Res<BlockRegistry>>,
+    mut statuses: EventWriter<ApplyStatus>,
+    query: Query<(Entity, &Transform), Living>,
+) {
+    let (Some(terrain), Some(registry)) = (terrain, registry) else {
+        return;
+    };
+    // Runs on the fixed timestep, so burning is refreshed on the same ticks whatever the frame rate
+    *elapsed += time.delta_seconds();
+    if *elapsed < 1.0 {
+        return;
+    }
+    *elapsed -= 1.0;
+
+    for (entity, transform) in query.iter() {
+        // Check the blocks at the entity's feet and body
+        let feet = terrain.world_to_block(transform.translation);
+        let damage = registry
+            .contact_damage(terrain.get_block(feet))
+            .max(registry.contact_damage(terrain.get_block(feet + IVec3::Y)));
+        if damage > 0 {
+            // Burning outlasts the contact, so stepping out of lava does not put the fire out at once
+            statuses.send(ApplyStatus {
+                target: entity,
+                effect: StatusEffect::new(StatusKind::Burning, damage as f32, BURNING_DURATION),
+            });
+        }
+    }
+}
+
+// Damage dealt per block a falling block dropped before landing on an entity
+const FALLING_BLOCK_DAMAGE: u32 = 2;
+
+// System to hurt entities caught under falling blocks when they land
+fn falling_block_damage_system(
+    mut landed: EventReader<BlockLanded>,
+    terrain: Option<Res<VoxelTerrain>>,
+    formula: Res<DamageFormula>,
+    mut damage_dealt: EventWriter<DamageDealt>,
+    query: Query<(Entity, &Transform, Option<&Defense>), Living>,
+) {
+    let Some(terrain) = terrain else {
+        landed.clear();
+        return;
+    };
+    for event in landed.read() {
+        for (entity, transform, defense) in query.iter() {
+            let feet = terrain.world_to_block(transform.translation);
+            if event.position == feet || event.position == feet + IVec3::Y {
+                let damage = Damage::of(DamageType::Physical, (event.distance * FALLING_BLOCK_DAMAGE) as f32);
+                let hit = formula.resolve_damage(damage, &defense.copied().unwrap_or_default());
+                debug!("Entity {:?} is crushed for {} damage", entity, hit.amount);
+                damage_dealt.send(DamageDealt::new(None, entity, hit));
+            }
         }
     }
 }
 
+// Distance within which enemies strike the player
+const ENEMY_ATTACK_RANGE: f32 = 1.5;
+
 // System to handle enemy AI
 fn enemy_ai_system(
     mut commands: Commands,
-    time: Res<Time>,
-    mut enemy_query: Query<(Entity, &Enemy, &mut Transform, &mut Velocity)>,
-    player_query: Query<(&Player, &Transform)>,
+    mut enemy_query: Query<(Entity, &Enemy, &Transform, &mut Velocity), Without<Dead>>,
+    player_query: Query<(Entity, &Player, &Transform), Without<Dead>>,
+    terrain: Option<Res<VoxelTerrain>>,
+    registry: Option<Res<BlockRegistry>>,
+    mut attacks: EventWriter<AttackIntent>,
 ) {
-    if let Some((_, player_transform)) = player_query.iter().next() {
-        for (entity, _, mut transform, mut velocity) in enemy_query.iter_mut() {
+    if let Some((player, _, player_transform)) = player_query.iter().next() {
+        for (entity, _, transform, mut velocity) in enemy_query.iter_mut() {
             let mut rng = rand::thread_rng();
             let player_position = player_transform.translation;
             let direction_to_player = player_position - transform.translation;
             let distance_to_player = direction_to_player.length();
 
-            // Simple AI: Move towards the player if they are within a certain range
-            if distance_to_player < 10.0 {
-                velocity.0 = direction_to_player.normalize() * rng.gen_range(0.5..1.5);
+            // Enemies can only see the player through air and transparent blocks
+            let can_see_player = match (&terrain, &registry) {
+                (Some(terrain), Some(registry)) => terrain.line_of_sight(
+                    transform.translation + Vec3::Y * 0.5,
+                    player_position + Vec3::Y * 0.5,
+                    |block| registry.is_transparent(block),
+                ),
+                _ => true,
+            };
+
+            // Simple AI: Move towards the player if they are within a certain range and in sight
+            let walk = if distance_to_player < 10.0 && can_see_player {
+                Vec3::new(direction_to_player.x, 0.0, direction_to_player.z).normalize_or_zero() * rng.gen_range(0.5..1.5)
             } else {
                 // Idle or random movement
-                velocity.0 = Vec3::new(rng.gen_range(-1.0..1.0), 0.0, rng.gen_range(-1.0..1.0)).normalize() * rng.gen_range(0.5..1.5);
-            }
+                Vec3::new(rng.gen_range(-1.0..1.0), 0.0, rng.gen_range(-1.0..1.0)).normalize_or_zero() * rng.gen_range(0.5..1.5)
+            };
+
+            // Only steer horizontally; the physics plugin applies the velocity and gravity
+            velocity.0 = Vec3::new(walk.x, velocity.0.y, walk.z);
 
-            // Update the enemy's position
-            transform.translation += velocity.0 * time.delta_seconds(); // Use delta_seconds for time step
-            println!("Enemy {:?} moves to {:?}", entity, transform.translation);
+            // Strike the player when close enough; the attack's cooldown decides whether it lands
+            if distance_to_player < ENEMY_ATTACK_RANGE && can_see_player {
+                attacks.send(AttackIntent {
+                    attacker: entity,
+                    target: AttackTarget::Entity(player),
+                });
+            }
         }
     }
 }
@@ -0,0 +1,323 @@
+use std::{fmt, fs, path::Path};
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use super::{Attack, Defense};
+
+// Kinds of damage a hit can carry; armor and resistances treat each kind separately
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum DamageType {
+    Physical,
+    Fire,
+    Frost,
+    Poison,
+    Lightning,
+}
+
+impl DamageType {
+    pub const ALL: [DamageType; 5] = [
+        DamageType::Physical,
+        DamageType::Fire,
+        DamageType::Frost,
+        DamageType::Poison,
+        DamageType::Lightning,
+    ];
+}
+
+// Amount of each type of damage in a hit
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(default)]
+pub struct Damage {
+    pub physical: f32,
+    pub fire: f32,
+    pub frost: f32,
+    pub poison: f32,
+    pub lightning: f32,
+}
+
+impl Damage {
+    // A hit of a single damage type
+    pub fn of(damage_type: DamageType, amount: f32) -> Self {
+        let mut damage = Damage::default();
+        *damage.get_mut(damage_type) = amount;
+        damage
+    }
+
+    pub fn get(&self, damage_type: DamageType) -> f32 {
+        match damage_type {
+            DamageType::Physical => self.physical,
+            DamageType::Fire => self.fire,
+            DamageType::Frost => self.frost,
+            DamageType::Poison => self.poison,
+            DamageType::Lightning => self.lightning,
+        }
+    }
+
+    pub fn get_mut(&mut self, damage_type: DamageType) -> &mut f32 {
+        match damage_type {
+            DamageType::Physical => &mut self.physical,
+            DamageType::Fire => &mut self.fire,
+            DamageType::Frost => &mut self.frost,
+            DamageType::Poison => &mut self.poison,
+            DamageType::Lightning => &mut self.lightning,
+        }
+    }
+
+    // Sum of every type of damage
+    pub fn total(&self) -> f32 {
+        DamageType::ALL.iter().map(|&damage_type| self.get(damage_type)).sum()
+    }
+
+    // Every type of damage multiplied by the same factor
+    pub fn scaled(mut self, factor: f32) -> Self {
+        for damage_type in DamageType::ALL {
+            *self.get_mut(damage_type) *= factor;
+        }
+        self
+    }
+}
+
+// Fraction of each type of damage an entity shrugs off; negative values are weaknesses that add damage
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(default)]
+pub struct Resistances {
+    pub physical: f32,
+    pub fire: f32,
+    pub frost: f32,
+    pub poison: f32,
+    pub lightning: f32,
+}
+
+impl Resistances {
+    pub fn get(&self, damage_type: DamageType) -> f32 {
+        match damage_type {
+            DamageType::Physical => self.physical,
+            DamageType::Fire => self.fire,
+            DamageType::Frost => self.frost,
+            DamageType::Poison => self.poison,
+            DamageType::Lightning => self.lightning,
+        }
+    }
+}
+
+// Tunable constants for turning attacks into damage, loaded from a RON file so designers can balance combat
+#[derive(Resource, Debug, Clone, PartialEq, Deserialize)]
+pub struct DamageFormula {
+    // Armor that mitigates half of the damage it applies to; mitigation is armor / (armor + armor_half_point),
+    // so every point of armor helps but each helps less than the one before
+    pub armor_half_point: f32,
+    // Most of a hit armor can ever mitigate
+    pub max_armor_mitigation: f32,
+    // Damage types armor applies to; the rest are only reduced by resistances
+    pub armor_types: Vec<DamageType>,
+    // Limits resistances are clamped to before they are applied
+    pub min_resistance: f32,
+    pub max_resistance: f32,
+    // Least damage a hit with any damage deals, so no amount of armor makes an entity invulnerable
+    pub minimum_damage: u32,
+}
+
+impl Default for DamageFormula {
+    fn default() -> Self {
+        DamageFormula {
+            armor_half_point: 50.0,
+            max_armor_mitigation: 0.8,
+            armor_types: vec![DamageType::Physical],
+            min_resistance: -1.0,
+            max_resistance: 0.9,
+            minimum_damage: 1,
+        }
+    }
+}
+
+// Errors that can occur while loading a damage formula
+#[derive(Debug)]
+pub enum DamageFormulaError {
+    Io(std::io::Error),
+    Parse(ron::Error),
+    // A constant is outside the range the formula works with
+    Invalid(&'static str),
+}
+
+impl fmt::Display for DamageFormulaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DamageFormulaError::Io(error) => write!(f, "could not read damage formula: {}", error),
+            DamageFormulaError::Parse(error) => write!(f, "could not parse damage formula: {}", error),
+            DamageFormulaError::Invalid(what) => write!(f, "damage formula has an invalid {}", what),
+        }
+    }
+}
+
+impl std::error::Error for DamageFormulaError {}
+
+// Outcome of one hit after crits, armor and resistances
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Hit {
+    // Damage of each type that got through
+    pub damage: Damage,
+    // Health the hit takes away
+    pub amount: u32,
+    pub critical: bool,
+}
+
+impl DamageFormula {
+    // Parse a damage formula from RON text, checking its constants
+    pub fn from_ron(source: &str) -> Result<Self, DamageFormulaError> {
+        let formula: DamageFormula = ron::de::from_str(source).map_err(DamageFormulaError::Parse)?;
+        if formula.armor_half_point <= 0.0 {
+            return Err(DamageFormulaError::Invalid("armor half point"));
+        }
+        if !(0.0..=1.0).contains(&formula.max_armor_mitigation) {
+            return Err(DamageFormulaError::Invalid("maximum armor mitigation"));
+        }
+        if formula.min_resistance > formula.max_resistance || formula.max_resistance > 1.0 {
+            return Err(DamageFormulaError::Invalid("resistance range"));
+        }
+        Ok(formula)
+    }
+
+    // Load a damage formula from a RON file on disk
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, DamageFormulaError> {
+        let source = fs::read_to_string(path).map_err(DamageFormulaError::Io)?;
+        DamageFormula::from_ron(&source)
+    }
+
+    // Fraction of damage that an amount of armor mitigates
+    pub fn armor_mitigation(&self, armor: f32) -> f32 {
+        let armor = armor.max(0.0);
+        (armor / (armor + self.armor_half_point)).min(self.max_armor_mitigation)
+    }
+
+    // Damage left of a hit after the target's armor and resistances
+    pub fn mitigate(&self, damage: Damage, defense: &Defense) -> Damage {
+        let armor = self.armor_mitigation(defense.armor);
+        let mut mitigated = damage;
+        for damage_type in DamageType::ALL {
+            let resistance = defense.resistances.get(damage_type).clamp(self.min_resistance, self.max_resistance);
+            let armor = if self.armor_types.contains(&damage_type) { armor } else { 0.0 };
+            *mitigated.get_mut(damage_type) = damage.get(damage_type).max(0.0) * (1.0 - armor) * (1.0 - resistance);
+        }
+        mitigated
+    }
+
+    // Outcome of a hit that cannot crit, such as damage from the terrain
+    pub fn resolve_damage(&self, damage: Damage, defense: &Defense) -> Hit {
+        let mitigated = self.mitigate(damage, defense);
+        let amount = if damage.total() > 0.0 {
+            (mitigated.total().round() as u32).max(self.minimum_damage)
+        } else {
+            0
+        };
+        Hit {
+            damage: mitigated,
+            amount,
+            critical: false,
+        }
+    }
+
+    // Outcome of an attack against a defense, where `roll` is a uniform random value in 0..1 deciding the crit
+    pub fn resolve_attack(&self, attack: &Attack, defense: &Defense, roll: f32) -> Hit {
+        let critical = roll < attack.crit_chance;
+        let damage = if critical { attack.damage.scaled(attack.crit_multiplier) } else { attack.damage };
+        Hit {
+            critical,
+            ..self.resolve_damage(damage, defense)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn armor_mitigation_is_capped() {
+        let formula = DamageFormula::default();
+        assert_eq!(formula.armor_mitigation(0.0), 0.0);
+        assert_eq!(formula.armor_mitigation(-20.0), 0.0);
+        assert!((formula.armor_mitigation(formula.armor_half_point) - 0.5).abs() < 1e-6);
+        // Far past the point where the curve would pass the cap, armor mitigates exactly the cap
+        assert_eq!(formula.armor_mitigation(1_000_000.0), formula.max_armor_mitigation);
+
+        let hit = formula.resolve_damage(Damage::of(DamageType::Physical, 100.0), &Defense::armor(1_000_000.0));
+        assert_eq!(hit.amount, 20);
+    }
+
+    #[test]
+    fn armor_only_applies_to_its_damage_types() {
+        let formula = DamageFormula::default();
+        let hit = formula.resolve_damage(Damage::of(DamageType::Fire, 100.0), &Defense::armor(formula.armor_half_point));
+        assert_eq!(hit.amount, 100);
+    }
+
+    #[test]
+    fn resistances_are_clamped() {
+        let formula = DamageFormula::default();
+        let defense = Defense {
+            resistances: Resistances {
+                fire: -5.0,
+                frost: 5.0,
+                ..default()
+            },
+            ..default()
+        };
+        // A weakness past the minimum only doubles the damage
+        let fire = formula.resolve_damage(Damage::of(DamageType::Fire, 10.0), &defense);
+        assert_eq!(fire.amount, 20);
+        // A resistance past the maximum still lets a tenth through
+        let frost = formula.resolve_damage(Damage::of(DamageType::Frost, 100.0), &defense);
+        assert_eq!(frost.amount, 10);
+    }
+
+    #[test]
+    fn hits_deal_minimum_damage() {
+        let formula = DamageFormula {
+            minimum_damage: 3,
+            ..DamageFormula::default()
+        };
+        let defense = Defense {
+            resistances: Resistances {
+                poison: 0.9,
+                ..default()
+            },
+            ..default()
+        };
+        let hit = formula.resolve_damage(Damage::of(DamageType::Poison, 1.0), &defense);
+        assert_eq!(hit.amount, 3);
+        // A hit that carries no damage does not get the minimum
+        assert_eq!(formula.resolve_damage(Damage::default(), &defense).amount, 0);
+    }
+
+    #[test]
+    fn roll_below_crit_chance_crits() {
+        let formula = DamageFormula::default();
+        let attack = Attack {
+            damage: Damage::of(DamageType::Physical, 10.0),
+            crit_chance: 0.25,
+            crit_multiplier: 2.0,
+        };
+        let crit = formula.resolve_attack(&attack, &Defense::default(), 0.0);
+        assert!(crit.critical);
+        assert_eq!(crit.amount, 20);
+
+        let normal = formula.resolve_attack(&attack, &Defense::default(), 0.25);
+        assert!(!normal.critical);
+        assert_eq!(normal.amount, 10);
+    }
+
+    #[test]
+    fn rejects_invalid_constants() {
+        let source = "(
+            armor_half_point: 50.0,
+            max_armor_mitigation: 1.5,
+            armor_types: [Physical],
+            min_resistance: -1.0,
+            max_resistance: 0.9,
+            minimum_damage: 1,
+        )";
+        assert!(matches!(DamageFormula::from_ron(source), Err(DamageFormulaError::Invalid(_))));
+        assert!(DamageFormula::from_ron(&source.replace("1.5", "0.8")).is_ok());
+    }
+}
@@ -0,0 +1,333 @@
+use bevy::prelude::*;
+
+use crate::items::Equipment;
+
+use super::{Attack, Defense, Health};
+
+// Character stats that equipment and buffs can change
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stat {
+    MaxHealth,
+    // Physical damage of the entity's attacks
+    Attack,
+    Armor,
+    CritChance,
+    CritMultiplier,
+    // Factor movement speed is multiplied by
+    Speed,
+    // Health restored each second
+    Regeneration,
+}
+
+impl Stat {
+    pub const ALL: [Stat; 7] = [
+        Stat::MaxHealth,
+        Stat::Attack,
+        Stat::Armor,
+        Stat::CritChance,
+        Stat::CritMultiplier,
+        Stat::Speed,
+        Stat::Regeneration,
+    ];
+
+    // Lowest and highest value the stat can end up at, however it is modified
+    pub fn limits(self) -> (f32, f32) {
+        match self {
+            Stat::MaxHealth | Stat::CritMultiplier => (1.0, f32::INFINITY),
+            Stat::CritChance => (0.0, 1.0),
+            _ => (0.0, f32::INFINITY),
+        }
+    }
+}
+
+// How a modifier changes a stat
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Modifier {
+    // Added to the base value
+    Flat(f32),
+    // Percentage of the base value and flat bonuses added on top; percentages from every source add together
+    Percent(f32),
+}
+
+// A change to one stat from an item or buff
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StatModifier {
+    pub stat: Stat,
+    pub modifier: Modifier,
+}
+
+impl StatModifier {
+    pub fn flat(stat: Stat, amount: f32) -> Self {
+        StatModifier {
+            stat,
+            modifier: Modifier::Flat(amount),
+        }
+    }
+
+    pub fn percent(stat: Stat, percent: f32) -> Self {
+        StatModifier {
+            stat,
+            modifier: Modifier::Percent(percent),
+        }
+    }
+}
+
+// Value of every stat; as a component, the final stats an entity ends up with after equipment and buffs
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct Stats {
+    pub max_health: f32,
+    pub attack: f32,
+    pub armor: f32,
+    pub crit_chance: f32,
+    pub crit_multiplier: f32,
+    pub speed: f32,
+    pub regeneration: f32,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Stats {
+            max_health: 100.0,
+            attack: 0.0,
+            armor: 0.0,
+            crit_chance: 0.05,
+            crit_multiplier: 1.5,
+            speed: 1.0,
+            regeneration: 0.0,
+        }
+    }
+}
+
+impl Stats {
+    pub fn get(&self, stat: Stat) -> f32 {
+        match stat {
+            Stat::MaxHealth => self.max_health,
+            Stat::Attack => self.attack,
+            Stat::Armor => self.armor,
+            Stat::CritChance => self.crit_chance,
+            Stat::CritMultiplier => self.crit_multiplier,
+            Stat::Speed => self.speed,
+            Stat::Regeneration => self.regeneration,
+        }
+    }
+
+    pub fn get_mut(&mut self, stat: Stat) -> &mut f32 {
+        match stat {
+            Stat::MaxHealth => &mut self.max_health,
+            Stat::Attack => &mut self.attack,
+            Stat::Armor => &mut self.armor,
+            Stat::CritChance => &mut self.crit_chance,
+            Stat::CritMultiplier => &mut self.crit_multiplier,
+            Stat::Speed => &mut self.speed,
+            Stat::Regeneration => &mut self.regeneration,
+        }
+    }
+
+    // Stats after modifiers: flat bonuses are added first, then the sum of percentage bonuses scales the result,
+    // so the order items are equipped in never matters
+    pub fn with_modifiers<'a>(&self, modifiers: impl IntoIterator<Item = &'a StatModifier>) -> Stats {
+        let mut flat = [0.0; Stat::ALL.len()];
+        let mut percent = [0.0; Stat::ALL.len()];
+        for modifier in modifiers {
+            match modifier.modifier {
+                Modifier::Flat(amount) => flat[modifier.stat as usize] += amount,
+                Modifier::Percent(amount) => percent[modifier.stat as usize] += amount,
+            }
+        }
+
+        let mut stats = *self;
+        for stat in Stat::ALL {
+            let (min, max) = stat.limits();
+            let value = stats.get_mut(stat);
+            *value = ((*value + flat[stat as usize]) * (1.0 + percent[stat as usize] / 100.0)).clamp(min, max);
+        }
+        stats
+    }
+}
+
+// Stats an entity has before equipment and buffs
+#[derive(Component, Debug, Clone, Copy, PartialEq, Default)]
+pub struct BaseStats(pub Stats);
+
+// Stat modifiers from sources other than equipment, such as potions and auras
+#[derive(Component, Debug, Clone, Default, PartialEq)]
+pub struct Buffs(pub Vec<StatModifier>);
+
+// Everything an entity's stats are worked out from, any of which but its base stats it may lack
+type StatInputs = (
+    Entity,
+    &'static BaseStats,
+    Option<&'static Equipment>,
+    Option<&'static Buffs>,
+);
+
+// Query filter for entities whose stats need working out again
+type StatInputsChanged = Or<(Changed<BaseStats>, Changed<Equipment>, Changed<Buffs>)>;
+
+// System to recompute the stats of entities whose base stats, equipment or buffs changed or were taken away,
+// carrying them over to the health, attack and defense combat works with
+pub fn stats_system(
+    mut commands: Commands,
+    changed: Query<Entity, StatInputsChanged>,
+    mut removed_equipment: RemovedComponents<Equipment>,
+    mut removed_buffs: RemovedComponents<Buffs>,
+    inputs: Query<StatInputs>,
+    mut combatants: Query<(Option<&mut Health>, Option<&Attack>, Option<&Defense>)>,
+) {
+    let mut entities: Vec<Entity> = changed.iter().chain(removed_equipment.read()).chain(removed_buffs.read()).collect();
+    entities.sort_unstable();
+    entities.dedup();
+    // Entities despawned since losing a component have nothing left to work out
+    for (entity, base, equipment, buffs) in inputs.iter_many(entities) {
+        let item_modifiers = equipment
+            .into_iter()
+            .flat_map(Equipment::items)
+            .flat_map(|item| item.effects.modifiers.iter());
+        let buff_modifiers = buffs.into_iter().flat_map(|buffs| buffs.0.iter());
+        let stats = base.0.with_modifiers(item_modifiers.chain(buff_modifiers));
+
+        let Ok((health, attack, defense)) = combatants.get_mut(entity) else {
+            continue;
+        };
+        if let Some(mut health) = health {
+            // The living keep the health they are missing, so equipping an item never looks like damage
+            // and taking it off and on again never heals; the dead stay dead
+            let max = stats.max_health.round() as u32;
+            let missing = health.max.saturating_sub(health.current);
+            health.current = if health.is_dead() { 0 } else { max.saturating_sub(missing).max(1) };
+            health.max = max;
+        }
+        // Attacks keep any other damage types they deal, and defenses their resistances
+        let mut attack = attack.copied().unwrap_or_default();
+        attack.damage.physical = stats.attack;
+        attack.crit_chance = stats.crit_chance;
+        attack.crit_multiplier = stats.crit_multiplier;
+        let mut defense = defense.copied().unwrap_or_default();
+        defense.armor = stats.armor;
+        commands.entity(entity).insert((stats, attack, defense));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::items::{Item, ItemEffects, ItemType};
+
+    #[test]
+    fn flat_modifiers_apply_before_percent_in_any_order() {
+        let base = Stats {
+            attack: 10.0,
+            ..Stats::default()
+        };
+        let modifiers = [
+            StatModifier::percent(Stat::Attack, 50.0),
+            StatModifier::flat(Stat::Attack, 10.0),
+            StatModifier::percent(Stat::Attack, 50.0),
+            StatModifier::flat(Stat::Speed, 0.5),
+        ];
+
+        // (10 + 10) * (1 + 0.5 + 0.5)
+        let stats = base.with_modifiers(&modifiers);
+        assert_eq!(stats.attack, 40.0);
+        assert_eq!(stats.speed, 1.5);
+        assert_eq!(stats.max_health, base.max_health);
+
+        let mut reversed = modifiers;
+        reversed.reverse();
+        assert_eq!(base.with_modifiers(&reversed), stats);
+    }
+
+    #[test]
+    fn modified_stats_stay_within_their_limits() {
+        let modifiers = [
+            StatModifier::flat(Stat::MaxHealth, -500.0),
+            StatModifier::percent(Stat::Armor, -200.0),
+            StatModifier::flat(Stat::CritChance, 3.0),
+            StatModifier::percent(Stat::CritMultiplier, -100.0),
+            StatModifier::flat(Stat::Speed, -2.0),
+        ];
+        let stats = Stats::default().with_modifiers(&modifiers);
+        assert_eq!(stats.max_health, 1.0);
+        assert_eq!(stats.armor, 0.0);
+        assert_eq!(stats.crit_chance, 1.0);
+        assert_eq!(stats.crit_multiplier, 1.0);
+        assert_eq!(stats.speed, 0.0);
+
+        let stats = Stats::default().with_modifiers(&[StatModifier::flat(Stat::CritChance, -1.0)]);
+        assert_eq!(stats.crit_chance, 0.0);
+    }
+
+    fn ring(modifier: StatModifier) -> Item {
+        Item {
+            name: "Ring".to_string(),
+            item_type: ItemType::Hat,
+            effects: ItemEffects {
+                modifiers: vec![modifier],
+            },
+            mesh_handle: Handle::default(),
+            material_handle: Handle::default(),
+        }
+    }
+
+    fn health(app: &App, entity: Entity) -> (u32, u32) {
+        let health = app.world.get::<Health>(entity).unwrap();
+        (health.current, health.max)
+    }
+
+    #[test]
+    fn equipping_keeps_missing_health() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins).add_systems(Update, stats_system);
+        let entity = app
+            .world
+            .spawn((BaseStats::default(), Health { current: 60, max: 100 }, Equipment::default()))
+            .id();
+        app.update();
+        assert_eq!(health(&app, entity), (60, 100));
+        assert_eq!(app.world.get::<Stats>(entity), Some(&Stats::default()));
+
+        app.world.get_mut::<Equipment>(entity).unwrap().hat = Some(ring(StatModifier::flat(Stat::MaxHealth, 50.0)));
+        app.update();
+        assert_eq!(health(&app, entity), (110, 150));
+
+        // Taking the item off again neither kills nor heals
+        app.world.get_mut::<Equipment>(entity).unwrap().hat = None;
+        app.update();
+        assert_eq!(health(&app, entity), (60, 100));
+
+        app.world.get_mut::<Health>(entity).unwrap().current = 30;
+        app.world.get_mut::<Equipment>(entity).unwrap().hat = Some(ring(StatModifier::flat(Stat::MaxHealth, -50.0)));
+        app.update();
+        assert_eq!(health(&app, entity), (1, 50));
+    }
+
+    #[test]
+    fn stats_are_recomputed_when_buffs_or_equipment_are_removed() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins).add_systems(Update, stats_system);
+        let entity = app
+            .world
+            .spawn((
+                BaseStats::default(),
+                Health::new(100),
+                Buffs(vec![StatModifier::percent(Stat::MaxHealth, 50.0)]),
+                Equipment {
+                    hat: Some(ring(StatModifier::flat(Stat::Armor, 5.0))),
+                    ..Equipment::default()
+                },
+            ))
+            .id();
+        app.update();
+        assert_eq!(health(&app, entity), (150, 150));
+        assert_eq!(app.world.get::<Defense>(entity).unwrap().armor, 5.0);
+
+        app.world.entity_mut(entity).remove::<Buffs>();
+        app.update();
+        assert_eq!(health(&app, entity), (100, 100));
+
+        app.world.entity_mut(entity).remove::<Equipment>();
+        app.update();
+        assert_eq!(app.world.get::<Defense>(entity).unwrap().armor, 0.0);
+        assert_eq!(app.world.get::<Stats>(entity), Some(&Stats::default()));
+    }
+}
@@ -0,0 +1,309 @@
+use bevy::prelude::*;
+
+// Kinds of timed effect an entity can be under
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StatusKind {
+    // Fire damage every tick
+    Burning,
+    // Poison damage every tick, for each stack
+    Poison,
+    // Movement speed reduced by the magnitude as a fraction
+    Slow,
+    // Unable to move or attack
+    Stun,
+    // Health restored every tick
+    Regeneration,
+    // Absorbs up to the magnitude in damage before breaking
+    Shield,
+}
+
+// What happens when an effect is applied to an entity already under the same effect from the same source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stacking {
+    // Restart the timer and keep the stronger magnitude
+    Refresh,
+    // Restart the timer and add a stack, up to the limit; each stack adds the magnitude again
+    Stack { max_stacks: u32 },
+}
+
+impl StatusKind {
+    pub fn stacking(self) -> Stacking {
+        match self {
+            StatusKind::Poison => Stacking::Stack { max_stacks: 5 },
+            _ => Stacking::Refresh,
+        }
+    }
+
+    // Whether the effect does something every tick rather than for as long as it lasts
+    pub fn ticks(self) -> bool {
+        matches!(self, StatusKind::Burning | StatusKind::Poison | StatusKind::Regeneration)
+    }
+}
+
+// One timed effect on an entity
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StatusEffect {
+    pub kind: StatusKind,
+    // Damage or healing per tick and stack, fraction of speed lost, or damage a shield has left to absorb
+    pub magnitude: f32,
+    // Seconds left before the effect wears off; infinite for effects that last until cleared
+    pub remaining: f32,
+    // Seconds between ticks of damage or healing
+    pub tick_interval: f32,
+    // Seconds until the next tick
+    pub until_tick: f32,
+    pub stacks: u32,
+    // Entity that caused the effect, credited with the damage it deals
+    pub source: Option<Entity>,
+}
+
+impl StatusEffect {
+    // An effect lasting `duration` seconds, ticking once a second
+    pub fn new(kind: StatusKind, magnitude: f32, duration: f32) -> Self {
+        StatusEffect {
+            kind,
+            magnitude,
+            remaining: duration,
+            tick_interval: 1.0,
+            until_tick: 1.0,
+            stacks: 1,
+            source: None,
+        }
+    }
+
+    pub fn from_source(mut self, source: Entity) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    pub fn with_tick_interval(mut self, tick_interval: f32) -> Self {
+        self.tick_interval = tick_interval;
+        self.until_tick = tick_interval;
+        self
+    }
+
+    // Damage or healing the effect does on each tick with all its stacks
+    pub fn strength(&self) -> f32 {
+        self.magnitude * self.stacks as f32
+    }
+}
+
+// Damage or healing a ticking effect did during an update
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StatusTick {
+    pub kind: StatusKind,
+    pub amount: f32,
+    pub source: Option<Entity>,
+}
+
+// Timed effects an entity is under
+#[derive(Component, Debug, Clone, Default, PartialEq)]
+pub struct StatusEffects {
+    pub effects: Vec<StatusEffect>,
+}
+
+impl StatusEffects {
+    // Add an effect following its kind's stacking rule; an effect without any duration clears the matching one instead
+    pub fn apply(&mut self, effect: StatusEffect) {
+        let existing = self
+            .effects
+            .iter()
+            .position(|existing| existing.kind == effect.kind && existing.source == effect.source);
+        if effect.remaining <= 0.0 {
+            if let Some(index) = existing {
+                self.effects.remove(index);
+            }
+            return;
+        }
+        let Some(index) = existing else {
+            self.effects.push(effect);
+            return;
+        };
+
+        let existing = &mut self.effects[index];
+        existing.remaining = existing.remaining.max(effect.remaining);
+        match effect.kind.stacking() {
+            Stacking::Refresh => existing.magnitude = existing.magnitude.max(effect.magnitude),
+            Stacking::Stack { max_stacks } => {
+                existing.magnitude = effect.magnitude;
+                existing.stacks = (existing.stacks + effect.stacks).min(max_stacks);
+            }
+        }
+    }
+
+    pub fn get(&self, kind: StatusKind) -> impl Iterator<Item = &StatusEffect> {
+        self.effects.iter().filter(move |effect| effect.kind == kind)
+    }
+
+    pub fn has(&self, kind: StatusKind) -> bool {
+        self.get(kind).next().is_some()
+    }
+
+    pub fn is_stunned(&self) -> bool {
+        self.has(StatusKind::Stun)
+    }
+
+    // Factor to scale movement by; the strongest slow wins and stuns stop movement entirely
+    pub fn speed_multiplier(&self) -> f32 {
+        if self.is_stunned() {
+            return 0.0;
+        }
+        let slow = self.get(StatusKind::Slow).map(|effect| effect.magnitude).fold(0.0, f32::max);
+        (1.0 - slow).clamp(0.0, 1.0)
+    }
+
+    // Soak up damage with any shields, returning the damage that gets through; broken shields are removed
+    pub fn absorb(&mut self, amount: u32) -> u32 {
+        let mut remaining = amount as f32;
+        for effect in self.effects.iter_mut().filter(|effect| effect.kind == StatusKind::Shield) {
+            let absorbed = remaining.min(effect.magnitude);
+            effect.magnitude -= absorbed;
+            remaining -= absorbed;
+        }
+        self.effects
+            .retain(|effect| effect.kind != StatusKind::Shield || effect.magnitude > 0.0);
+        remaining.round() as u32
+    }
+
+    // Advance every effect's timers, returning the ticks of damage and healing that fell due and dropping expired effects
+    pub fn tick(&mut self, delta: f32) -> Vec<StatusTick> {
+        let mut ticks = Vec::new();
+        for effect in self.effects.iter_mut() {
+            // A tick that falls due after the effect has worn off does not happen
+            let elapsed = delta.min(effect.remaining);
+            effect.remaining -= delta;
+            if !effect.kind.ticks() || effect.tick_interval <= 0.0 {
+                continue;
+            }
+            effect.until_tick -= elapsed;
+            while effect.until_tick <= 0.0 {
+                effect.until_tick += effect.tick_interval;
+                ticks.push(StatusTick {
+                    kind: effect.kind,
+                    amount: effect.strength(),
+                    source: effect.source,
+                });
+            }
+        }
+        self.effects.retain(|effect| effect.remaining > 0.0);
+        ticks
+    }
+}
+
+// Event sent to put a timed effect on an entity, from attacks, items or the terrain
+#[derive(Event, Debug, Clone, Copy, PartialEq)]
+pub struct ApplyStatus {
+    pub target: Entity,
+    pub effect: StatusEffect,
+}
+
+// System to add requested effects to their targets, giving entities their first status effects as needed
+pub fn apply_status_system(
+    mut commands: Commands,
+    mut requests: EventReader<ApplyStatus>,
+    mut query: Query<&mut StatusEffects>,
+) {
+    // Entities without effects yet collect theirs here so several effects in one frame are all kept
+    let mut added: Vec<(Entity, StatusEffects)> = Vec::new();
+    for request in requests.read() {
+        if let Ok(mut effects) = query.get_mut(request.target) {
+            effects.apply(request.effect);
+        } else if let Some((_, effects)) = added.iter_mut().find(|(entity, _)| *entity == request.target) {
+            effects.apply(request.effect);
+        } else {
+            let mut effects = StatusEffects::default();
+            effects.apply(request.effect);
+            added.push((request.target, effects));
+        }
+    }
+    for (entity, effects) in added {
+        if let Some(mut entity) = commands.get_entity(entity) {
+            entity.insert(effects);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn poison_stacks_up_to_the_limit() {
+        let mut effects = StatusEffects::default();
+        for _ in 0..8 {
+            effects.apply(StatusEffect::new(StatusKind::Poison, 2.0, 5.0));
+        }
+        assert_eq!(effects.effects.len(), 1);
+        assert_eq!(effects.effects[0].stacks, 5);
+        assert_eq!(effects.effects[0].strength(), 10.0);
+
+        // Poison from someone else is tracked separately
+        let source = Entity::from_raw(7);
+        effects.apply(StatusEffect::new(StatusKind::Poison, 2.0, 5.0).from_source(source));
+        assert_eq!(effects.effects.len(), 2);
+        assert_eq!(effects.effects[1].stacks, 1);
+    }
+
+    #[test]
+    fn refresh_keeps_the_stronger_magnitude_and_longer_duration() {
+        let mut effects = StatusEffects::default();
+        effects.apply(StatusEffect::new(StatusKind::Slow, 0.5, 2.0));
+        effects.apply(StatusEffect::new(StatusKind::Slow, 0.2, 6.0));
+        assert_eq!(effects.effects.len(), 1);
+        assert_eq!(effects.effects[0].magnitude, 0.5);
+        assert_eq!(effects.effects[0].remaining, 6.0);
+        assert_eq!(effects.effects[0].stacks, 1);
+
+        effects.apply(StatusEffect::new(StatusKind::Slow, 0.8, 1.0));
+        assert_eq!(effects.effects[0].magnitude, 0.8);
+        assert_eq!(effects.effects[0].remaining, 6.0);
+        assert_eq!(effects.speed_multiplier(), 1.0 - 0.8);
+
+        // An effect without any duration clears it
+        effects.apply(StatusEffect::new(StatusKind::Slow, 0.0, 0.0));
+        assert!(!effects.has(StatusKind::Slow));
+    }
+
+    #[test]
+    fn effects_do_not_tick_after_they_expire() {
+        let mut effects = StatusEffects::default();
+        effects.apply(StatusEffect::new(StatusKind::Burning, 3.0, 2.5));
+
+        // Only the ticks at one and two seconds fall inside the effect's duration
+        let ticks = effects.tick(10.0);
+        assert_eq!(ticks.len(), 2);
+        assert!(ticks.iter().all(|tick| tick.kind == StatusKind::Burning && tick.amount == 3.0));
+        assert!(effects.effects.is_empty());
+        assert!(effects.tick(10.0).is_empty());
+
+        // Non-ticking effects just wear off
+        effects.apply(StatusEffect::new(StatusKind::Stun, 0.0, 1.0));
+        assert!(effects.tick(0.5).is_empty());
+        assert!(effects.is_stunned());
+        assert!(effects.tick(0.5).is_empty());
+        assert!(!effects.is_stunned());
+    }
+
+    #[test]
+    fn ticks_follow_the_tick_interval() {
+        let mut effects = StatusEffects::default();
+        effects.apply(StatusEffect::new(StatusKind::Regeneration, 1.0, 10.0).with_tick_interval(0.5));
+        assert!(effects.tick(0.4).is_empty());
+        assert_eq!(effects.tick(0.2).len(), 1);
+        assert_eq!(effects.tick(1.0).len(), 2);
+    }
+
+    #[test]
+    fn shields_absorb_damage_until_they_break() {
+        let mut effects = StatusEffects::default();
+        effects.apply(StatusEffect::new(StatusKind::Shield, 10.0, f32::INFINITY));
+
+        assert_eq!(effects.absorb(4), 0);
+        assert_eq!(effects.effects[0].magnitude, 6.0);
+
+        // The shield breaks and the rest of the damage gets through
+        assert_eq!(effects.absorb(10), 4);
+        assert!(!effects.has(StatusKind::Shield));
+        assert_eq!(effects.absorb(5), 5);
+    }
+}
@@ -1,5 +1,8 @@
 use bevy::prelude::*;
 
+use crate::character_model::{load_model, HAT_MODEL};
+use crate::combat::{Stat, StatModifier};
+
 // Define the types of items available in the game
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ItemType {
@@ -9,7 +12,7 @@ pub enum ItemType {
 }
 
 // Define the properties of an item
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Item {
     pub name: String,
     pub item_type: ItemType,
@@ -18,12 +21,10 @@ pub struct Item {
     pub material_handle: Handle<StandardMaterial>, // Added field for the material handle
 }
 
-// Define the effects that an item can have on the character
-#[derive(Debug, Clone)]
+// Define the effects that an item can have on the character while it is equipped
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct ItemEffects {
-    pub health_bonus: i32,
-    pub attack_bonus: i32,
-    pub defense_bonus: i32,
+    pub modifiers: Vec<StatModifier>,
 }
 
 // Define the inventory to manage and store items
@@ -40,45 +41,70 @@ pub struct Equipment {
     pub armor: Option<Item>,
 }
 
+impl Equipment {
+    // Items in every slot that holds one
+    pub fn items(&self) -> impl Iterator<Item = &Item> {
+        [&self.hat, &self.weapon, &self.armor].into_iter().flatten()
+    }
+}
+
+// Define a struct to hold the meshes and materials items are drawn with
+#[derive(Resource, Debug, Default, Clone)]
+pub struct ItemAssets {
+    pub hat_mesh: Handle<Mesh>,
+    pub hat_material: Handle<StandardMaterial>,
+}
+
 // Plugin to set up item systems
 pub struct ItemPlugin;
 
 impl Plugin for ItemPlugin {
     fn build(&self, app: &mut App) {
         app
+            .init_resource::<ItemAssets>()
+            // Models are loaded once up front instead of every time an item is added
+            .add_systems(Startup, setup_item_assets)
             .add_systems(add_item_system)
             .add_systems(remove_item_system)
             .add_systems(use_item_system);
     }
 }
 
+// System to load the item models, sharing the meshes characters wear
+fn setup_item_assets(
+    mut item_assets: ResMut<ItemAssets>,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    item_assets.hat_mesh = load_model(&asset_server, HAT_MODEL);
+    // The model's colours come from its palette as vertex colours
+    item_assets.hat_material = materials.add(StandardMaterial {
+        base_color: Color::WHITE,
+        ..Default::default()
+    });
+}
+
 // System to add items to the inventory
 fn add_item_system(
     mut query: Query<&mut Inventory>,
-    asset_server: Res<AssetServer>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut meshes: ResMut<Assets<Mesh>>,
+    item_assets: Res<ItemAssets>,
 ) {
     // Logic for adding items to the inventory would be implemented here
     // This is a placeholder example of adding an item to the first inventory found
     for mut inventory in query.iter_mut() {
-        let hat_material_handle = materials.add(StandardMaterial {
-            base_color: Color::rgb(0.1, 0.1, 0.1),
-            ..Default::default()
-        });
-        // Corrected the creation of the Cuboid mesh with the half_size field
-        let hat_mesh_handle = meshes.add(Mesh::from(Cuboid { half_size: Vec3::new(0.25, 0.25, 0.25) }));
-
         let item = Item {
             name: "Mystic Hat".to_string(),
             item_type: ItemType::Hat,
             effects: ItemEffects {
-                health_bonus: 5,
-                attack_bonus: 2,
-                defense_bonus: 3,
+                modifiers: vec![
+                    StatModifier::flat(Stat::MaxHealth, 5.0),
+                    StatModifier::flat(Stat::Attack, 2.0),
+                    StatModifier::flat(Stat::Armor, 3.0),
+                    StatModifier::percent(Stat::CritChance, 10.0),
+                ],
             },
-            mesh_handle: hat_mesh_handle, // Assign the mesh handle
-            material_handle: hat_material_handle, // Assign the material handle
+            mesh_handle: item_assets.hat_mesh.clone(), // Assign the mesh handle
+            material_handle: item_assets.hat_material.clone(), // Assign the material handle
         };
         inventory.items.push(item);
         break; // Only add to the first inventory for this example
@@ -98,7 +124,7 @@ fn remove_item_system(
     }
 }
 
-// System to use items and apply their effects
+// System to use items; the stats system applies the effects of whatever ends up equipped
 fn use_item_system(
     mut query: Query<(&mut Inventory, &mut Equipment)>,
     // Additional parameters for the system would be defined here
@@ -106,11 +132,16 @@ fn use_item_system(
     // Logic for using items and applying their effects would be implemented here
     // This is a placeholder example of equipping the first item from the inventory
     for (mut inventory, mut equipment) in query.iter_mut() {
-        if let Some(item) = inventory.items.get(0).cloned() {
-            match item.item_type {
-                ItemType::Hat => equipment.hat = Some(item),
-                ItemType::Weapon => equipment.weapon = Some(item),
-                ItemType::Armor => equipment.armor = Some(item),
+        if let Some(item) = inventory.items.get(0) {
+            let slot = match item.item_type {
+                ItemType::Hat => &mut equipment.bypass_change_detection().hat,
+                ItemType::Weapon => &mut equipment.bypass_change_detection().weapon,
+                ItemType::Armor => &mut equipment.bypass_change_detection().armor,
+            };
+            // Only write the slot when it changes, so the stats are not worked out again every frame
+            if slot.as_ref() != Some(item) {
+                *slot = Some(item.clone());
+                equipment.set_changed();
             }
         }
         break; // Only equip to the first character for this example
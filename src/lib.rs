@@ -1,11 +1,10 @@
-use bevy::{
-    prelude::*,
-    app::{AppExit, startup_stage::StartupStage},
-    input::keyboard::Input,
-};
+use bevy::{app::AppExit, prelude::*};
 
 mod voxel_terrain;
-use voxel_terrain::VoxelTerrain;
+use voxel_terrain::{
+    BlockRegistry, ChunkLoader, ChunkMaterial, GeneratorSettings, Structure, StructureSpawn, VoxelTerrain, VoxelTerrainPlugin,
+    WorldStorage,
+};
 
 // Import the character plugin module
 mod character_model;
@@ -17,84 +16,132 @@ use animation::AnimationPlugin;
 
 // Import the combat plugin module
 mod combat;
-use combat::CombatPlugin;
+use combat::{BaseStats, Checkpoint, CombatPlugin, DamageFormula, Health, Player, Velocity};
+
+// Import the physics plugin module
+mod physics;
+use physics::{CharacterController, PhysicsPlugin};
 
 // Import the items plugin module
 mod items;
 use items::ItemPlugin;
 
-#[derive(Component)]
-struct Player;
+// Import the sprite sheet plugin module
+mod spritesheet;
+use spritesheet::SpritesheetPlugin;
+
+// Chance of a shrine standing in each chunk column
+const SHRINE_CHANCE: f32 = 0.02;
 
 pub fn run_app() {
+    let mut generator_settings = GeneratorSettings::default();
+    let shrine = Structure::load("assets/structures/shrine.ron").expect("failed to load shrine structure");
+    generator_settings.structures.push(StructureSpawn::new(shrine, SHRINE_CHANCE));
+
     App::new()
         .add_plugins(DefaultPlugins)
-        .insert_resource(VoxelTerrain::new(Vec3::new(100.0, 100.0, 100.0), 1.0))
+        .insert_resource(BlockRegistry::load("assets/blocks.ron").expect("failed to load block registry"))
+        .insert_resource(DamageFormula::load("assets/damage.ron").expect("failed to load damage formula"))
+        .insert_resource(
+            VoxelTerrain::new(Vec3::new(100.0, 100.0, 100.0), 1.0)
+                .with_generator_settings(generator_settings)
+                .with_storage(WorldStorage::new("saves/world")),
+        )
+        // Add the VoxelTerrainPlugin to the app
+        .add_plugins(VoxelTerrainPlugin)
         // Add the CharacterPlugin to the app
-        .add_plugin(CharacterPlugin)
+        .add_plugins(CharacterPlugin)
         // Add the AnimationPlugin to the app
-        .add_plugin(AnimationPlugin)
+        .add_plugins(AnimationPlugin)
         // Add the CombatPlugin to the app
-        .add_plugin(CombatPlugin)
+        .add_plugins(CombatPlugin)
+        // Add the PhysicsPlugin to the app
+        .add_plugins(PhysicsPlugin)
         // Add the ItemPlugin to the app
-        .add_plugin(ItemPlugin)
-        // Initialize the startup system
-        .add_startup_system_to_stage(StartupStage::PreStartup, setup)
-        .add_startup_system_to_stage(StartupStage::PreStartup, voxel_terrain_setup)
+        .add_plugins(ItemPlugin)
+        // Add the SpritesheetPlugin to the app
+        .add_plugins(SpritesheetPlugin)
+        // Initialize the startup systems; the player is placed once the terrain generator exists
+        .add_systems(PreStartup, (voxel_terrain_setup, setup).chain())
         // Add systems to the app with the correct schedule label
-        .add_system(player_input_system)
-        .add_system(exit_on_esc_system)
+        .add_systems(Update, (player_input_system, exit_on_esc_system))
         .run();
 }
 
 fn setup(
     mut commands: Commands,
+    voxel_terrain: Res<VoxelTerrain>,
 ) {
     // Spawn a 2D camera entity
-    commands.spawn().insert_bundle(Camera2dBundle::default());
-    // Spawn the player entity
-    commands.spawn().insert_bundle(SpriteBundle {
-        transform: Transform {
-            translation: Vec3::new(0.0, -215.0, 0.0),
-            scale: Vec3::new(0.5, 0.5, 1.0),
+    commands.spawn(Camera2dBundle::default());
+    // Spawn the player entity standing on the surface at the world origin
+    let surface = voxel_terrain.generator.as_ref().map_or(0, |generator| generator.height_at(0, 0));
+    let spawn_point = Vec3::new(0.0, (surface + 1) as f32 * voxel_terrain.voxel_size, 0.0);
+    let base_stats = BaseStats::default();
+    commands.spawn((
+        SpriteBundle {
+            transform: Transform {
+                translation: spawn_point,
+                scale: Vec3::new(0.5, 0.5, 1.0),
+                ..Default::default()
+            },
             ..Default::default()
         },
-        ..Default::default()
-    })
-    .insert(Player);
+        Player,
+        // Take damage, and come back where the player started after dying
+        Health::new(base_stats.0.max_health.round() as u32),
+        Checkpoint(spawn_point),
+        base_stats,
+        // Walk on the terrain instead of passing through it
+        Velocity(Vec3::ZERO),
+        CharacterController::default(),
+        // Stream terrain chunks in around the player
+        ChunkLoader::default(),
+    ));
 }
 
 fn voxel_terrain_setup(
     mut commands: Commands,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut materials: ResMut<Assets<ChunkMaterial>>,
     mut meshes: ResMut<Assets<Mesh>>,
-    voxel_terrain: Res<VoxelTerrain>,
+    mut voxel_terrain: ResMut<VoxelTerrain>,
+    block_registry: Res<BlockRegistry>,
 ) {
-    voxel_terrain.generate(&mut commands, &mut materials, &mut meshes);
+    voxel_terrain.generate(&mut commands, &mut materials, &mut meshes, &block_registry);
 }
 
+// Walking speed of the player in world units per second
+const PLAYER_SPEED: f32 = 4.0;
+
 fn player_input_system(
-    keyboard_input: Res<Input<KeyCode>>,
-    mut query: Query<&mut Transform, With<Player>>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut query: Query<(&mut Velocity, &mut CharacterController), With<Player>>,
 ) {
-    for mut transform in query.iter_mut() {
+    for (mut velocity, mut controller) in query.iter_mut() {
+        let mut direction = Vec3::ZERO;
         if keyboard_input.pressed(KeyCode::ArrowUp) {
-            transform.translation.y += 2.;
+            direction.z -= 1.;
         }
         if keyboard_input.pressed(KeyCode::ArrowDown) {
-            transform.translation.y -= 2.;
+            direction.z += 1.;
         }
         if keyboard_input.pressed(KeyCode::ArrowLeft) {
-            transform.translation.x -= 2.;
+            direction.x -= 1.;
         }
         if keyboard_input.pressed(KeyCode::ArrowRight) {
-            transform.translation.x += 2.;
+            direction.x += 1.;
+        }
+        // The physics plugin moves the player; gravity keeps control of the vertical speed
+        let walk = direction.normalize_or_zero() * PLAYER_SPEED;
+        velocity.0 = Vec3::new(walk.x, velocity.0.y, walk.z);
+        if keyboard_input.just_pressed(KeyCode::Space) {
+            controller.jump = true;
         }
     }
 }
 
 fn exit_on_esc_system(
-    keyboard_input: Res<Input<KeyCode>>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
     mut exit: EventWriter<AppExit>,
 ) {
     if keyboard_input.just_pressed(KeyCode::Escape) {
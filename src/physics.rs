@@ -0,0 +1,283 @@
+use bevy::prelude::*;
+
+use crate::combat::{Stats, StatusEffects, Velocity};
+use crate::voxel_terrain::{world_to_chunk, BlockId, BlockRegistry, ChunkTasks, VoxelTerrain, AIR};
+
+// Kinematic collision box and movement settings for an entity that walks on the voxel terrain.
+// The entity's translation is the centre of the bottom of its box.
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct CharacterController {
+    // Half the width of the box along x and z
+    pub half_width: f32,
+    pub height: f32,
+    // Downward acceleration in world units per second squared
+    pub gravity: f32,
+    // Fastest the character can fall
+    pub terminal_velocity: f32,
+    // Upward speed given by a jump
+    pub jump_speed: f32,
+    // Tallest ledge the character climbs onto without jumping
+    pub step_height: f32,
+    // Set to jump on the next tick; ignored while airborne
+    pub jump: bool,
+    // Whether the character was standing on a block after its last move
+    pub grounded: bool,
+}
+
+impl Default for CharacterController {
+    fn default() -> Self {
+        CharacterController {
+            half_width: 0.3,
+            height: 1.8,
+            gravity: 24.0,
+            terminal_velocity: 50.0,
+            jump_speed: 8.0,
+            step_height: 1.0,
+            jump: false,
+            grounded: false,
+        }
+    }
+}
+
+impl CharacterController {
+    // Corners of the collision box for a character at the given translation
+    pub fn bounds(&self, translation: Vec3) -> (Vec3, Vec3) {
+        let half = Vec3::new(self.half_width, 0.0, self.half_width);
+        (translation - half, translation + half + Vec3::Y * self.height)
+    }
+}
+
+// Plugin to move entities by their velocity, colliding characters with the terrain
+pub struct PhysicsPlugin;
+
+impl Plugin for PhysicsPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            // Movement runs on the fixed timestep so jumps and falls are the same whatever the frame rate
+            .add_systems(FixedUpdate, (character_controller_system, free_movement_system));
+    }
+}
+
+// Whether characters collide with a block
+fn blocks_movement(registry: Option<&BlockRegistry>, block: BlockId) -> bool {
+    block != AIR && registry.is_none_or(|registry| registry.is_solid(block))
+}
+
+// Move a character's box along one axis, climbing ledges up to its step height when a wall is in the way.
+// Returns the offset applied to the translation.
+fn move_horizontally(
+    terrain: &VoxelTerrain,
+    controller: &CharacterController,
+    translation: Vec3,
+    axis: usize,
+    distance: f32,
+    is_solid: impl Fn(BlockId) -> bool + Copy,
+) -> Vec3 {
+    let (min, max) = controller.bounds(translation);
+    let mut offset = Vec3::ZERO;
+    offset[axis] = terrain.sweep_box(min, max, axis, distance, is_solid);
+    if offset[axis] == distance || !controller.grounded || controller.step_height <= 0.0 {
+        return offset;
+    }
+
+    // Lift the box as far as the step height allows, move it, then settle it back down onto the ledge
+    let lift = terrain.sweep_box(min, max, 1, controller.step_height, is_solid);
+    let (min, max) = controller.bounds(translation + Vec3::Y * lift);
+    let mut stepped = Vec3::Y * lift;
+    stepped[axis] = terrain.sweep_box(min, max, axis, distance, is_solid);
+    if stepped[axis].abs() <= offset[axis].abs() {
+        return offset;
+    }
+    let (min, max) = controller.bounds(translation + stepped);
+    stepped.y += terrain.sweep_box(min, max, 1, -lift, is_solid);
+    stepped
+}
+
+// Components that speed up or slow down how fast an entity moves, any of which it may lack
+type SpeedModifiers = (Option<&'static StatusEffects>, Option<&'static Stats>);
+
+// Factor to scale movement by from the entity's speed stat, slows and stuns
+fn speed_multiplier((effects, stats): (Option<&StatusEffects>, Option<&Stats>)) -> f32 {
+    effects.map_or(1.0, StatusEffects::speed_multiplier) * stats.map_or(1.0, |stats| stats.speed)
+}
+
+// System to move characters by their velocity with gravity, jumping and collision against the terrain
+fn character_controller_system(
+    time: Res<Time>,
+    terrain: Option<Res<VoxelTerrain>>,
+    registry: Option<Res<BlockRegistry>>,
+    tasks: Option<Res<ChunkTasks>>,
+    mut query: Query<(&mut Transform, &mut Velocity, &mut CharacterController, SpeedModifiers)>,
+) {
+    // Characters wait for terrain to stand on
+    let Some(terrain) = terrain else {
+        return;
+    };
+    let registry = registry.as_deref();
+    let is_solid = |block: BlockId| blocks_movement(registry, block);
+    let delta = time.delta_seconds();
+
+    for (mut transform, mut velocity, mut controller, speed_modifiers) in query.iter_mut() {
+        // Hold characters still while the ground under them is still being generated, so they do not fall through it
+        let (chunk, _) = world_to_chunk(terrain.world_to_block(transform.translation));
+        if tasks.as_ref().is_some_and(|tasks| tasks.is_generating(chunk) || tasks.is_generating(chunk - IVec3::Y)) {
+            continue;
+        }
+
+        // Speed, slows and stuns change walking and jumping but never gravity
+        let speed = speed_multiplier(speed_modifiers);
+        if controller.jump && controller.grounded && speed > 0.0 {
+            velocity.0.y = controller.jump_speed;
+        }
+        controller.jump = false;
+        velocity.0.y = (velocity.0.y - controller.gravity * delta).max(-controller.terminal_velocity);
+        let motion = Vec3::new(velocity.0.x * speed, velocity.0.y, velocity.0.z * speed) * delta;
+
+        // Vertical movement first, so a character standing on the ground can step up ledges this tick
+        let (min, max) = controller.bounds(transform.translation);
+        let vertical = terrain.sweep_box(min, max, 1, motion.y, is_solid);
+        transform.translation.y += vertical;
+        controller.grounded = motion.y < 0.0 && vertical > motion.y;
+        if vertical != motion.y {
+            velocity.0.y = 0.0;
+        }
+
+        // Each horizontal axis moves separately, so blocked movement slides along walls
+        for axis in [0, 2] {
+            let offset = move_horizontally(&terrain, &controller, transform.translation, axis, motion[axis], is_solid);
+            transform.translation += offset;
+            if offset[axis] != motion[axis] {
+                velocity.0[axis] = 0.0;
+            }
+        }
+    }
+}
+
+// System to move entities without a character controller straight along their velocity
+fn free_movement_system(
+    time: Res<Time>,
+    mut query: Query<(&mut Transform, &Velocity, SpeedModifiers), Without<CharacterController>>,
+) {
+    for (mut transform, velocity, speed_modifiers) in query.iter_mut() {
+        let speed = speed_multiplier(speed_modifiers);
+        transform.translation += velocity.0 * speed * time.delta_seconds();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+    use crate::voxel_terrain::test_registry;
+
+    // Headless app on a stone floor whose top is at y = 1, plus the given blocks, advancing one fixed tick per update
+    fn physics_app(blocks: impl IntoIterator<Item = IVec3>) -> App {
+        let registry = test_registry();
+        let stone = registry.id("stone").unwrap();
+        let mut terrain = VoxelTerrain::new(Vec3::ZERO, 1.0);
+        for x in -8..24 {
+            for z in -8..24 {
+                terrain.set_block(IVec3::new(x, 0, z), stone);
+            }
+        }
+        for block in blocks {
+            terrain.set_block(block, stone);
+        }
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, PhysicsPlugin))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(1.0 / 64.0)))
+            .insert_resource(registry)
+            .insert_resource(terrain);
+        app
+    }
+
+    // Drop a character at a position and let it come to rest on the ground
+    fn spawn_character(app: &mut App, translation: Vec3) -> Entity {
+        let character = app
+            .world
+            .spawn((Transform::from_translation(translation), Velocity(Vec3::ZERO), CharacterController::default()))
+            .id();
+        run(app, 64);
+        character
+    }
+
+    fn run(app: &mut App, ticks: usize) {
+        for _ in 0..ticks {
+            app.update();
+        }
+    }
+
+    // A wall along the z axis at x = 2, `height` blocks high
+    fn wall(height: i32) -> impl Iterator<Item = IVec3> {
+        (1..=height).flat_map(|y| (-8..24).map(move |z| IVec3::new(2, y, z)))
+    }
+
+    #[test]
+    fn falling_character_lands_on_the_ground() {
+        let mut app = physics_app([]);
+        let character = app
+            .world
+            .spawn((Transform::from_xyz(0.5, 6.0, 0.5), Velocity(Vec3::ZERO), CharacterController::default()))
+            .id();
+        app.update();
+        assert!(!app.world.get::<CharacterController>(character).unwrap().grounded);
+        run(&mut app, 64);
+
+        let translation = app.world.get::<Transform>(character).unwrap().translation;
+        assert!((translation.y - 1.0).abs() < 1e-3, "character rests at y = {}", translation.y);
+        assert!(app.world.get::<CharacterController>(character).unwrap().grounded);
+
+        // Jumping lifts the character off the ground, and gravity brings it back
+        app.world.get_mut::<CharacterController>(character).unwrap().jump = true;
+        run(&mut app, 8);
+        assert!(app.world.get::<Transform>(character).unwrap().translation.y > 1.5);
+        assert!(!app.world.get::<CharacterController>(character).unwrap().grounded);
+        run(&mut app, 64);
+        assert!((app.world.get::<Transform>(character).unwrap().translation.y - 1.0).abs() < 1e-3);
+        assert!(app.world.get::<CharacterController>(character).unwrap().grounded);
+    }
+
+    #[test]
+    fn character_climbs_a_one_block_ledge() {
+        let mut app = physics_app(wall(1));
+        let character = spawn_character(&mut app, Vec3::new(0.5, 1.0, 0.5));
+        app.world.get_mut::<Velocity>(character).unwrap().0.x = 4.0;
+        run(&mut app, 32);
+
+        let translation = app.world.get::<Transform>(character).unwrap().translation;
+        assert!(translation.x > 2.3, "character stopped at x = {}", translation.x);
+        assert!((translation.y - 2.0).abs() < 1e-3, "character is at y = {}", translation.y);
+        assert!(app.world.get::<CharacterController>(character).unwrap().grounded);
+    }
+
+    #[test]
+    fn character_stops_at_a_two_block_wall() {
+        let mut app = physics_app(wall(2));
+        let character = spawn_character(&mut app, Vec3::new(0.5, 1.0, 0.5));
+        app.world.get_mut::<Velocity>(character).unwrap().0.x = 4.0;
+        run(&mut app, 32);
+
+        let translation = app.world.get::<Transform>(character).unwrap().translation;
+        let half_width = CharacterController::default().half_width;
+        assert!((translation.x - (2.0 - half_width)).abs() < 1e-3, "character is at x = {}", translation.x);
+        assert!((translation.y - 1.0).abs() < 1e-3);
+        assert_eq!(app.world.get::<Velocity>(character).unwrap().0.x, 0.0);
+    }
+
+    #[test]
+    fn character_slides_along_a_wall() {
+        let mut app = physics_app(wall(2));
+        let character = spawn_character(&mut app, Vec3::new(1.5, 1.0, 0.5));
+        app.world.get_mut::<Velocity>(character).unwrap().0 = Vec3::new(4.0, 0.0, 4.0);
+        run(&mut app, 32);
+
+        // Half a second at 4 blocks per second along z, while the wall holds x back
+        let translation = app.world.get::<Transform>(character).unwrap().translation;
+        let half_width = CharacterController::default().half_width;
+        assert!((translation.x - (2.0 - half_width)).abs() < 1e-3, "character is at x = {}", translation.x);
+        assert!((translation.z - 2.5).abs() < 1e-3, "character is at z = {}", translation.z);
+    }
+}
@@ -0,0 +1,322 @@
+use std::{collections::HashMap, fmt};
+
+use bevy::{
+    prelude::*,
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext, LoadDirectError},
+    utils::BoxedFuture,
+};
+
+// A sprite sheet image together with the named regions described by its XML file
+#[derive(Asset, TypePath, Debug, Clone)]
+pub struct Spritesheet {
+    pub image: Handle<Image>,
+    pub layout: Handle<TextureAtlasLayout>,
+    // Size of the sheet image in pixels
+    pub size: Vec2,
+    // Pixel rectangle of every sprite, in layout index order
+    pub rects: Vec<Rect>,
+    indices: HashMap<String, usize>,
+}
+
+impl Spritesheet {
+    // Layout index of the sprite with the given name, e.g. "stone_gold.png"
+    pub fn index(&self, name: &str) -> Option<usize> {
+        self.indices.get(name).copied()
+    }
+
+    // Pixel rectangle of the sprite with the given name
+    pub fn rect(&self, name: &str) -> Option<Rect> {
+        self.index(name).map(|index| self.rects[index])
+    }
+
+    // Rectangle of the sprite with the given name in normalized 0..1 texture coordinates
+    pub fn uv_rect(&self, name: &str) -> Option<Rect> {
+        self.rect(name).map(|rect| Rect::from_corners(rect.min / self.size, rect.max / self.size))
+    }
+
+    // Texture atlas component selecting the sprite with the given name
+    pub fn texture_atlas(&self, name: &str) -> Option<TextureAtlas> {
+        self.index(name).map(|index| TextureAtlas {
+            layout: self.layout.clone(),
+            index,
+        })
+    }
+
+    // Names of every sprite in the sheet
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.indices.keys().map(String::as_str)
+    }
+}
+
+// Contents of a `<TextureAtlas>` XML file before any assets are created
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpritesheetXml {
+    pub image_path: String,
+    pub sub_textures: Vec<(String, Rect)>,
+}
+
+impl SpritesheetXml {
+    // Check that every sub-texture lies inside the sheet image; the XML does not record the image size,
+    // so it has to come from the image itself, which may have padding past the last sprite
+    pub fn check_bounds(&self, image_size: Vec2) -> Result<(), SpritesheetError> {
+        match self.sub_textures.iter().find(|(_, rect)| rect.min.min_element() < 0.0 || rect.max.cmpgt(image_size).any()) {
+            Some((name, _)) => Err(SpritesheetError::OutOfBounds(name.clone(), image_size)),
+            None => Ok(()),
+        }
+    }
+}
+
+// Errors that can occur while loading a sprite sheet
+#[derive(Debug)]
+pub enum SpritesheetError {
+    Io(std::io::Error),
+    Utf8(std::str::Utf8Error),
+    Parse(String),
+    // The sheet image could not be loaded
+    Image(Box<LoadDirectError>),
+    // The named sprite reaches past the edge of the sheet image of the given size
+    OutOfBounds(String, Vec2),
+}
+
+impl fmt::Display for SpritesheetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpritesheetError::Io(error) => write!(f, "could not read sprite sheet: {}", error),
+            SpritesheetError::Utf8(error) => write!(f, "sprite sheet is not valid UTF-8: {}", error),
+            SpritesheetError::Parse(message) => write!(f, "could not parse sprite sheet: {}", message),
+            SpritesheetError::Image(error) => write!(f, "could not load sprite sheet image: {}", error),
+            SpritesheetError::OutOfBounds(name, size) => {
+                write!(f, "sprite '{}' lies outside the {}x{} sheet image", name, size.x, size.y)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SpritesheetError {}
+
+impl From<std::io::Error> for SpritesheetError {
+    fn from(error: std::io::Error) -> Self {
+        SpritesheetError::Io(error)
+    }
+}
+
+// Find an attribute value inside a tag's `name="value"` or `name='value'` pairs
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = tag;
+    while let Some(equals) = rest.find('=') {
+        let key = rest[..equals].split_whitespace().last();
+        let value = rest[equals + 1..].trim_start();
+        let quote = value.chars().next().filter(|&c| c == '"' || c == '\'')?;
+        let end = value[1..].find(quote)? + 1;
+        if key == Some(name) {
+            return Some(&value[1..end]);
+        }
+        rest = &value[end + 1..];
+    }
+    None
+}
+
+// Find a numeric attribute inside a tag
+fn number_attribute(tag: &str, name: &str) -> Result<f32, SpritesheetError> {
+    let value = attribute(tag, name).ok_or_else(|| SpritesheetError::Parse(format!("missing attribute '{}'", name)))?;
+    value.parse().map_err(|_| SpritesheetError::Parse(format!("attribute '{}' is not a number: {}", name, value)))
+}
+
+// Collect the attribute text of every `<tag ...>` element in the source
+fn elements<'a>(source: &'a str, tag: &str) -> Vec<&'a str> {
+    let open = format!("<{}", tag);
+    source
+        .match_indices(open.as_str())
+        .filter_map(|(start, _)| {
+            let rest = &source[start + open.len()..];
+            // Skip longer tag names that share this prefix
+            if !rest.starts_with(|c: char| c.is_whitespace() || c == '>' || c == '/') {
+                return None;
+            }
+            rest.find('>').map(|end| rest[..end].trim_end_matches('/'))
+        })
+        .collect()
+}
+
+// Parse a Kenney `<TextureAtlas><SubTexture name x y width height/></TextureAtlas>` file
+pub fn parse_spritesheet_xml(source: &str) -> Result<SpritesheetXml, SpritesheetError> {
+    let atlas = elements(source, "TextureAtlas")
+        .first()
+        .copied()
+        .ok_or_else(|| SpritesheetError::Parse("missing <TextureAtlas> element".to_string()))?;
+    let image_path = attribute(atlas, "imagePath")
+        .ok_or_else(|| SpritesheetError::Parse("missing attribute 'imagePath'".to_string()))?
+        .to_string();
+
+    let mut sub_textures = Vec::new();
+    for sub_texture in elements(source, "SubTexture") {
+        let name = attribute(sub_texture, "name")
+            .ok_or_else(|| SpritesheetError::Parse("missing attribute 'name'".to_string()))?;
+        let min = Vec2::new(number_attribute(sub_texture, "x")?, number_attribute(sub_texture, "y")?);
+        let size = Vec2::new(number_attribute(sub_texture, "width")?, number_attribute(sub_texture, "height")?);
+        sub_textures.push((name.to_string(), Rect::from_corners(min, min + size)));
+    }
+
+    Ok(SpritesheetXml { image_path, sub_textures })
+}
+
+// Asset loader turning `.atlas.xml` sprite sheet files into `Spritesheet` assets
+#[derive(Default)]
+pub struct SpritesheetLoader;
+
+impl AssetLoader for SpritesheetLoader {
+    type Asset = Spritesheet;
+    type Settings = ();
+    type Error = SpritesheetError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Spritesheet, SpritesheetError>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let xml = parse_spritesheet_xml(std::str::from_utf8(&bytes).map_err(SpritesheetError::Utf8)?)?;
+
+            // The image path is relative to the XML file; the image is loaded here because only it knows the sheet size
+            let image_path = load_context
+                .path()
+                .parent()
+                .map_or_else(|| xml.image_path.clone().into(), |parent| parent.join(&xml.image_path));
+            let image = load_context
+                .load_direct(image_path)
+                .await
+                .map_err(|error| SpritesheetError::Image(Box::new(error)))?
+                .take::<Image>()
+                .ok_or_else(|| SpritesheetError::Parse(format!("{} is not an image", xml.image_path)))?;
+            let size = image.size_f32();
+            xml.check_bounds(size)?;
+            let image = load_context.add_labeled_asset("image".to_string(), image);
+
+            let mut layout = TextureAtlasLayout::new_empty(size);
+            let mut rects = Vec::new();
+            let mut indices = HashMap::new();
+            for (name, rect) in xml.sub_textures {
+                indices.insert(name, layout.add_texture(rect));
+                rects.push(rect);
+            }
+            let layout = load_context.add_labeled_asset("layout".to_string(), layout);
+
+            Ok(Spritesheet {
+                image,
+                layout,
+                size,
+                rects,
+                indices,
+            })
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        // Only sprite sheet atlases, so other XML assets are left to their own loaders
+        &["atlas.xml"]
+    }
+}
+
+// Handles to the sprite sheets bundled in assets/Spritesheets
+#[derive(Resource, Debug, Clone, Default)]
+pub struct Spritesheets {
+    pub tiles: Handle<Spritesheet>,
+    pub items: Handle<Spritesheet>,
+    pub characters: Handle<Spritesheet>,
+    pub particles: Handle<Spritesheet>,
+}
+
+// Plugin to register the sprite sheet loader and load the bundled sheets
+pub struct SpritesheetPlugin;
+
+impl Plugin for SpritesheetPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_asset::<Spritesheet>()
+            .init_asset_loader::<SpritesheetLoader>()
+            .init_resource::<Spritesheets>()
+            .add_systems(Startup, load_spritesheets);
+    }
+}
+
+// System to start loading the bundled sprite sheets
+fn load_spritesheets(
+    asset_server: Res<AssetServer>,
+    mut spritesheets: ResMut<Spritesheets>,
+) {
+    spritesheets.tiles = asset_server.load("Spritesheets/spritesheet_tiles.atlas.xml");
+    spritesheets.items = asset_server.load("Spritesheets/spritesheet_items.atlas.xml");
+    spritesheets.characters = asset_server.load("Spritesheets/spritesheet_characters.atlas.xml");
+    spritesheets.particles = asset_server.load("Spritesheets/spritesheet_particles.atlas.xml");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The start of the Kenney tile sheet's atlas file
+    const TILES_SNIPPET: &str = r#"<TextureAtlas imagePath="spritesheet_tiles.png">
+	<SubTexture name="brick_grey.png" x="512" y="256" width="128" height="128"/>
+	<SubTexture name="brick_red.png" x="1024" y="384" width="128" height="128"/>
+</TextureAtlas>"#;
+
+    #[test]
+    fn parses_kenney_atlas() {
+        let xml = parse_spritesheet_xml(TILES_SNIPPET).expect("snippet parses");
+        assert_eq!(xml.image_path, "spritesheet_tiles.png");
+        assert_eq!(
+            xml.sub_textures,
+            vec![
+                ("brick_grey.png".to_string(), Rect::new(512.0, 256.0, 640.0, 384.0)),
+                ("brick_red.png".to_string(), Rect::new(1024.0, 384.0, 1152.0, 512.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_bundled_atlases() {
+        for (name, image_size) in [("tiles", Vec2::new(1152.0, 1280.0)), ("particles", Vec2::new(72.0, 84.0))] {
+            let path = format!("{}/assets/Spritesheets/spritesheet_{}.atlas.xml", env!("CARGO_MANIFEST_DIR"), name);
+            let xml = parse_spritesheet_xml(&std::fs::read_to_string(path).unwrap()).expect("bundled atlas parses");
+            assert_eq!(xml.image_path, format!("spritesheet_{}.png", name));
+            assert!(!xml.sub_textures.is_empty());
+            xml.check_bounds(image_size).expect("every sprite lies inside the image");
+        }
+    }
+
+    #[test]
+    fn parses_single_quoted_attributes() {
+        let xml = parse_spritesheet_xml("<TextureAtlas imagePath='sheet.png'><SubTexture name='a b.png' x='1' y = '2' width='3' height='4'/></TextureAtlas>")
+            .expect("single quotes parse");
+        assert_eq!(xml.image_path, "sheet.png");
+        assert_eq!(xml.sub_textures, vec![("a b.png".to_string(), Rect::new(1.0, 2.0, 4.0, 6.0))]);
+    }
+
+    #[test]
+    fn rejects_missing_attribute() {
+        let source = TILES_SNIPPET.replace(r#" width="128""#, "");
+        let error = parse_spritesheet_xml(&source).unwrap_err();
+        assert!(matches!(error, SpritesheetError::Parse(message) if message.contains("'width'")));
+        let error = parse_spritesheet_xml("<TextureAtlas></TextureAtlas>").unwrap_err();
+        assert!(matches!(error, SpritesheetError::Parse(message) if message.contains("'imagePath'")));
+    }
+
+    #[test]
+    fn rejects_bad_number() {
+        let source = TILES_SNIPPET.replace(r#"x="512""#, r#"x="5l2""#);
+        let error = parse_spritesheet_xml(&source).unwrap_err();
+        assert!(matches!(error, SpritesheetError::Parse(message) if message.contains("'x'") && message.contains("5l2")));
+    }
+
+    #[test]
+    fn rejects_sprites_outside_the_image() {
+        let xml = parse_spritesheet_xml(TILES_SNIPPET).unwrap();
+        // Padding past the last sprite is fine
+        xml.check_bounds(Vec2::new(2048.0, 2048.0)).unwrap();
+        let error = xml.check_bounds(Vec2::new(1100.0, 1280.0)).unwrap_err();
+        assert!(matches!(error, SpritesheetError::OutOfBounds(name, _) if name == "brick_red.png"));
+    }
+}
@@ -1,66 +1,552 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
 use bevy::{
     prelude::*,
-    render::{
-        mesh::{Mesh, Indices, PrimitiveTopology}, // Corrected import path for PrimitiveTopology
-        render_asset::RenderAssetUsages,
-    },
-    pbr::PbrBundle,
+    pbr::{MaterialMeshBundle, MaterialPlugin, PbrPlugin},
 };
 
-// Removed unused imports
+mod chunk;
+pub use chunk::{BlockId, Chunk, AIR, CHUNK_SIZE, CHUNK_VOLUME};
+
+mod mesher;
+pub use mesher::{mesh_chunk, ChunkMeshData, Face, MesherSettings};
+
+mod blocks;
+pub use blocks::{BlockRegistry, BlockRegistryError, BlockTextures, BlockType, FluidProperties};
+#[cfg(test)]
+pub(crate) use blocks::test_registry;
+
+mod atlas;
+pub use atlas::{apply_block_atlas, AtlasTiling, BlockAtlas, ChunkMaterial};
+
+mod noise;
+pub use noise::Noise;
+
+mod decoration;
+pub use decoration::{Decoration, DecorationSpawn, MAX_DECORATION_HEIGHT, MAX_DECORATION_RADIUS};
+
+mod biome;
+pub use biome::{default_biomes, select_biome, Biome, Climate, EnemySpawn};
+
+mod generation;
+pub use generation::{Column, GeneratorSettings, OreVein, TerrainGenerator};
+
+mod dungeon;
+pub use dungeon::{Dungeon, DungeonConnection, DungeonError, DungeonGenerator, DungeonRoom, DungeonSettings, RoomKind, RoomStructure};
+
+mod structure;
+pub use structure::{MarkerKind, Rotation, Structure, StructureError, StructureMarker, StructureSpawn, AIR_BLOCK_NAME, KEEP_BLOCK};
+
+mod vox;
+pub use vox::{default_vox_palette, VoxError, VoxFile, VoxLoader, VoxLoaderSettings, VoxModel, VoxPalette};
+
+mod raycast;
+pub use raycast::RaycastHit;
+
+mod collision;
+
+mod editing;
+pub use editing::{apply_block_edits, BlockChanged, BlockEdit};
+
+mod lighting;
+pub use lighting::{light_brightness, update_lighting, ChunkLight, LightChannel, MAX_LIGHT};
+
+mod fluids;
+pub use fluids::{tick_fluids, wake_fluids, FluidReaction, FluidSimulation};
+
+mod falling;
+pub use falling::{tick_falling_blocks, wake_falling_blocks, BlockLanded, FallingBlocks};
+
+mod lod;
+pub use lod::{lod_scale, update_chunk_lods, LodSettings, MAX_LOD};
+
+mod region;
+pub use region::{chunk_to_region, Region, RegionError, WorldStorage, REGION_SIZE, REGION_VERSION};
+
+mod streaming;
+pub use streaming::{
+    poll_generation_tasks, remesh_dirty_chunks, save_terrain_on_exit, save_unloaded_chunks, update_chunk_loaders, ChunkLoader,
+    ChunkTasks,
+};
+
+// Ordering of terrain systems within a frame
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TerrainSet {
+    // Load and unload chunks around chunk loaders
+    Stream,
+    // Apply block edit events to chunk data and update lighting
+    Edit,
+    // Rebuild meshes for chunks whose data changed
+    Mesh,
+}
+
+// Plugin to set up terrain streaming, runtime editing, fluids, falling blocks and remeshing
+pub struct VoxelTerrainPlugin;
+
+impl Plugin for VoxelTerrainPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_event::<BlockEdit>()
+            .add_event::<BlockChanged>()
+            .add_event::<BlockLanded>()
+            .init_resource::<ChunkTasks>()
+            .init_resource::<FluidSimulation>()
+            .init_resource::<FallingBlocks>()
+            .init_resource::<LodSettings>()
+            .configure_sets(Update, (TerrainSet::Stream, TerrainSet::Edit, TerrainSet::Mesh).chain())
+            // Streaming generates chunks, so it waits until the block registry is available
+            .add_systems(
+                Update,
+                (update_chunk_loaders, save_unloaded_chunks, poll_generation_tasks)
+                    .chain()
+                    .in_set(TerrainSet::Stream)
+                    .run_if(resource_exists::<BlockRegistry>),
+            )
+            .add_systems(
+                Update,
+                (
+                    apply_block_edits,
+                    update_lighting.run_if(resource_exists::<BlockRegistry>),
+                    wake_fluids,
+                    wake_falling_blocks.run_if(resource_exists::<BlockRegistry>),
+                )
+                    .chain()
+                    .in_set(TerrainSet::Edit),
+            )
+            // Fluids and falling blocks move on the fixed timestep so they run at the same rate whatever the frame rate
+            .add_systems(
+                FixedUpdate,
+                (tick_fluids, tick_falling_blocks).chain().run_if(resource_exists::<BlockRegistry>),
+            )
+            // Meshing needs the render assets, so headless apps only track dirty chunks
+            .add_systems(
+                Update,
+                (update_chunk_lods, remesh_dirty_chunks.run_if(resource_exists::<Assets<Mesh>>))
+                    .chain()
+                    .in_set(TerrainSet::Mesh),
+            )
+            .add_systems(Last, save_terrain_on_exit);
+
+        // Models load through the asset server, which headless apps may not have
+        if app.is_plugin_added::<AssetPlugin>() {
+            app.init_asset_loader::<VoxLoader>();
+        }
+
+        // Chunk materials need the PBR renderer, which headless apps do not have
+        if app.is_plugin_added::<PbrPlugin>() {
+            app
+                .add_plugins(MaterialPlugin::<ChunkMaterial>::default())
+                .add_systems(
+                    Update,
+                    apply_block_atlas.run_if(resource_exists::<BlockRegistry>).before(TerrainSet::Mesh),
+                );
+        }
+    }
+}
 
 // Define the voxel terrain
 pub struct VoxelTerrain {
+    // Region generated up front by `generate`; chunk loaders stream the rest of the world
     pub size: Vec3,
     pub voxel_size: f32,
+    // Seed for procedural generation; the same seed always produces the same world
+    pub seed: u64,
+    pub generator_settings: GeneratorSettings,
+    // Generator built from the seed and settings on first use; reset it to None after changing either
+    pub generator: Option<Arc<TerrainGenerator>>,
+    // Block data for every loaded chunk, keyed by chunk coordinate
+    pub chunks: HashMap<IVec3, Chunk>,
+    // Loaded chunks whose blocks changed after generation
+    pub edited_chunks: HashSet<IVec3>,
+    // Sunlight and block light for every loaded chunk
+    pub light: HashMap<IVec3, ChunkLight>,
+    // Loaded chunks whose light has not been computed yet
+    pub unlit_chunks: HashSet<IVec3>,
+    // Edited chunks kept in memory after streaming out, so they come back unchanged
+    pub unloaded_chunks: HashMap<IVec3, Chunk>,
+    // Region files edited chunks are saved to, if the world is persisted
+    pub storage: Option<WorldStorage>,
+    // Chunks within a loader's simulation radius
+    pub simulated_chunks: HashSet<IVec3>,
+    // Level of detail of chunks meshed below full resolution
+    pub chunk_lods: HashMap<IVec3, u8>,
+    // Entities rendering each chunk and their meshes, keyed by chunk coordinate
+    pub chunk_entities: HashMap<IVec3, Entity>,
+    pub chunk_meshes: HashMap<IVec3, Handle<Mesh>>,
+    // Material shared by every chunk entity
+    pub chunk_material: Handle<ChunkMaterial>,
+    // Tiles of every block in the tile sheet, once the sheet has loaded
+    pub block_atlas: Option<Arc<BlockAtlas>>,
+    // Chunks whose block data changed since they were last meshed
+    pub dirty_chunks: HashSet<IVec3>,
+    // Options used when building chunk meshes
+    pub mesher_settings: MesherSettings,
 }
 
 // Implement the Resource trait for VoxelTerrain
 impl Resource for VoxelTerrain {}
 
+// Split a world block coordinate into its chunk coordinate and local coordinate within that chunk
+pub fn world_to_chunk(world: IVec3) -> (IVec3, UVec3) {
+    let size = IVec3::splat(CHUNK_SIZE);
+    (world.div_euclid(size), world.rem_euclid(size).as_uvec3())
+}
+
+// Combine a chunk coordinate and local coordinate back into a world block coordinate
+pub fn chunk_to_world(chunk: IVec3, local: UVec3) -> IVec3 {
+    chunk * CHUNK_SIZE + local.as_ivec3()
+}
+
 impl VoxelTerrain {
     // Initialize the voxel terrain with a given size and voxel size
     pub fn new(size: Vec3, voxel_size: f32) -> Self {
-        VoxelTerrain { size, voxel_size }
+        VoxelTerrain {
+            size,
+            voxel_size,
+            seed: 0,
+            generator_settings: GeneratorSettings::default(),
+            generator: None,
+            chunks: HashMap::new(),
+            edited_chunks: HashSet::new(),
+            light: HashMap::new(),
+            unlit_chunks: HashSet::new(),
+            unloaded_chunks: HashMap::new(),
+            storage: None,
+            simulated_chunks: HashSet::new(),
+            chunk_lods: HashMap::new(),
+            chunk_entities: HashMap::new(),
+            chunk_meshes: HashMap::new(),
+            chunk_material: Handle::default(),
+            block_atlas: None,
+            dirty_chunks: HashSet::new(),
+            mesher_settings: MesherSettings::default(),
+        }
+    }
+
+    // Use the given seed for procedural generation
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    // Generate the world from the given settings
+    pub fn with_generator_settings(mut self, settings: GeneratorSettings) -> Self {
+        self.generator_settings = settings;
+        self.generator = None;
+        self
+    }
+
+    // Save edited chunks to, and load them back from, the given storage
+    pub fn with_storage(mut self, storage: WorldStorage) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    // Convert a position in world space into the coordinate of the block containing it
+    pub fn world_to_block(&self, position: Vec3) -> IVec3 {
+        (position / self.voxel_size).floor().as_ivec3()
+    }
+
+    // Biome at a position in world space, chosen from the same climate maps the generator uses
+    pub fn biome_at(&self, position: Vec3) -> Option<&Biome> {
+        let block = self.world_to_block(position);
+        let climate = Climate::new(self.seed, self.generator_settings.climate_frequency);
+        let (temperature, humidity) = climate.sample(block.x, block.z);
+        let biomes = &self.generator_settings.biomes;
+        select_biome(biomes, temperature, humidity).map(|index| &biomes[index])
+    }
+
+    // Get the chunk at a chunk coordinate if it is loaded
+    pub fn chunk(&self, chunk: IVec3) -> Option<&Chunk> {
+        self.chunks.get(&chunk)
+    }
+
+    // Get mutable access to the chunk at a chunk coordinate if it is loaded
+    pub fn chunk_mut(&mut self, chunk: IVec3) -> Option<&mut Chunk> {
+        self.chunks.get_mut(&chunk)
+    }
+
+    // Read a block by world block coordinate, treating unloaded chunks as air
+    pub fn get_block(&self, world: IVec3) -> BlockId {
+        let (chunk, local) = world_to_chunk(world);
+        self.get_block_in_chunk(chunk, local)
+    }
+
+    // Write a block by world block coordinate, returning the block that was replaced
+    pub fn set_block(&mut self, world: IVec3, block: BlockId) -> BlockId {
+        let (chunk, local) = world_to_chunk(world);
+        self.set_block_in_chunk(chunk, local, block)
+    }
+
+    // Read a block by chunk coordinate and local coordinate
+    pub fn get_block_in_chunk(&self, chunk: IVec3, local: UVec3) -> BlockId {
+        self.chunks.get(&chunk).map_or(AIR, |chunk| chunk.get(local))
+    }
+
+    // Write a block by chunk coordinate and local coordinate, restoring or generating the chunk first if it is not loaded
+    // so the edit lands on its terrain. Without a generator, missing chunks start out as air.
+    pub fn set_block_in_chunk(&mut self, chunk: IVec3, local: UVec3, block: BlockId) -> BlockId {
+        if !self.chunks.contains_key(&chunk) && !self.restore_chunk(chunk) {
+            let generated = match &self.generator {
+                Some(generator) => generator.generate_chunk(chunk),
+                None if block == AIR => return AIR,
+                None => Chunk::default(),
+            };
+            self.insert_chunk(chunk, generated);
+        }
+        let previous = self.chunks.get_mut(&chunk).unwrap().set(local, block);
+        if previous != block {
+            self.mark_dirty(chunk, local);
+            self.edited_chunks.insert(chunk);
+        }
+        previous
+    }
+
+    // Add a freshly loaded chunk, flagging it for lighting and it and its neighbours for remeshing
+    pub fn insert_chunk(&mut self, position: IVec3, chunk: Chunk) {
+        self.chunks.insert(position, chunk);
+        self.light.insert(position, ChunkLight::default());
+        self.unlit_chunks.insert(position);
+        self.dirty_chunks.insert(position);
+        for face in Face::ALL {
+            if self.chunks.contains_key(&(position + face.normal())) {
+                self.dirty_chunks.insert(position + face.normal());
+            }
+        }
+    }
+
+    // Remove a chunk and its entity, keeping its data if it was edited
+    pub fn unload_chunk(&mut self, position: IVec3, commands: &mut Commands) {
+        if let Some(chunk) = self.chunks.remove(&position) {
+            if self.edited_chunks.remove(&position) {
+                self.unloaded_chunks.insert(position, chunk);
+            }
+        }
+        self.light.remove(&position);
+        self.unlit_chunks.remove(&position);
+        self.chunk_lods.remove(&position);
+        if let Some(entity) = self.chunk_entities.remove(&position) {
+            commands.entity(entity).despawn();
+        }
+        // Dropping the last handle frees the mesh asset
+        self.chunk_meshes.remove(&position);
+        self.dirty_chunks.remove(&position);
+    }
+
+    // Load a previously edited chunk from memory or storage, returning whether one was found
+    pub fn restore_chunk(&mut self, position: IVec3) -> bool {
+        let chunk = match self.unloaded_chunks.remove(&position) {
+            Some(chunk) => Some(chunk),
+            None => self.storage.as_mut().and_then(|storage| {
+                storage.load_chunk(position).unwrap_or_else(|error| {
+                    error!("Could not load chunk {}: {}", position, error);
+                    None
+                })
+            }),
+        };
+        match chunk {
+            Some(chunk) => {
+                self.insert_chunk(position, chunk);
+                self.edited_chunks.insert(position);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Write edited chunks that were streamed out to storage, freeing their memory
+    pub fn save_unloaded_chunks(&mut self) -> Result<(), RegionError> {
+        let Some(storage) = self.storage.as_mut() else {
+            return Ok(());
+        };
+        storage.save_chunks(self.unloaded_chunks.iter().map(|(position, chunk)| (*position, chunk)))?;
+        self.unloaded_chunks.clear();
+        Ok(())
+    }
+
+    // Write every edited chunk, loaded or not, to storage
+    pub fn save(&mut self) -> Result<(), RegionError> {
+        self.save_unloaded_chunks()?;
+        let Some(storage) = self.storage.as_mut() else {
+            return Ok(());
+        };
+        storage.save_chunks(self.edited_chunks.iter().filter_map(|position| Some((*position, self.chunks.get(position)?))))
+    }
+
+    // Whether the chunk at a chunk coordinate is loaded and within a loader's simulation radius.
+    // Without any loaders every loaded chunk is simulated.
+    pub fn is_simulated(&self, position: IVec3) -> bool {
+        self.chunks.contains_key(&position) && (self.simulated_chunks.is_empty() || self.simulated_chunks.contains(&position))
+    }
+
+    // Get the generator for this terrain's seed and settings, creating it if needed
+    pub fn generator(&mut self, registry: &BlockRegistry) -> Arc<TerrainGenerator> {
+        self.generator
+            .get_or_insert_with(|| Arc::new(TerrainGenerator::new(self.seed, self.generator_settings.clone(), registry)))
+            .clone()
+    }
+
+    // Copy a chunk and its neighbours, with their light and level of detail, into a standalone terrain, so it can be meshed off the main thread
+    pub fn snapshot(&self, position: IVec3) -> VoxelTerrain {
+        let mut snapshot = VoxelTerrain::new(self.size, self.voxel_size);
+        snapshot.mesher_settings = self.mesher_settings;
+        snapshot.block_atlas = self.block_atlas.clone();
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let neighbor = position + IVec3::new(dx, dy, dz);
+                    if let Some(chunk) = self.chunks.get(&neighbor) {
+                        snapshot.chunks.insert(neighbor, chunk.clone());
+                    }
+                    if let Some(light) = self.light.get(&neighbor) {
+                        snapshot.light.insert(neighbor, light.clone());
+                    }
+                    if let Some(&lod) = self.chunk_lods.get(&neighbor) {
+                        snapshot.chunk_lods.insert(neighbor, lod);
+                    }
+                }
+            }
+        }
+        snapshot
+    }
+
+    // Flag a chunk for remeshing after the block at `local` changed, along with any neighbour sharing that face
+    pub fn mark_dirty(&mut self, chunk: IVec3, local: UVec3) {
+        self.dirty_chunks.insert(chunk);
+        for axis in 0..3 {
+            let mut offset = IVec3::ZERO;
+            if local[axis] == 0 {
+                offset[axis] = -1;
+            } else if local[axis] == CHUNK_SIZE as u32 - 1 {
+                offset[axis] = 1;
+            } else {
+                continue;
+            }
+            self.dirty_chunks.insert(chunk + offset);
+        }
+    }
+
+    // Generate and light block data for every chunk overlapping the terrain volume
+    pub fn fill(&mut self, registry: &BlockRegistry) {
+        let generator = self.generator(registry);
+        let half_size = (self.size / 2.0).as_ivec3();
+        let (min_chunk, _) = world_to_chunk(-half_size);
+        let (max_chunk, _) = world_to_chunk(half_size - IVec3::ONE);
+        for x in min_chunk.x..=max_chunk.x {
+            for y in min_chunk.y..=max_chunk.y {
+                for z in min_chunk.z..=max_chunk.z {
+                    let position = IVec3::new(x, y, z);
+                    if self.restore_chunk(position) {
+                        continue;
+                    }
+                    let chunk = generator.generate_chunk(position);
+                    if !chunk.is_empty() {
+                        self.insert_chunk(position, chunk);
+                    }
+                }
+            }
+        }
+        self.light_pending_chunks(registry);
+    }
+
+    // Write a batch of blocks, such as a structure or dungeon, generating or restoring the chunks it reaches first.
+    // The touched chunks are lit again from scratch afterwards, which is cheaper than relighting every block.
+    pub fn stamp_blocks(&mut self, blocks: impl IntoIterator<Item = (IVec3, BlockId)>, registry: &BlockRegistry) {
+        let generator = self.generator(registry);
+        let mut touched = HashSet::new();
+        for (world, block) in blocks {
+            let (position, _) = world_to_chunk(world);
+            if touched.insert(position) && !self.chunks.contains_key(&position) && !self.restore_chunk(position) {
+                self.insert_chunk(position, generator.generate_chunk(position));
+            }
+            self.set_block(world, block);
+        }
+        for position in touched {
+            self.light.insert(position, ChunkLight::default());
+            self.unlit_chunks.insert(position);
+        }
+        self.light_pending_chunks(registry);
+    }
+
+    // Build mesh data for a loaded chunk using the terrain's mesher settings
+    pub fn build_chunk_mesh(&self, position: IVec3, registry: &BlockRegistry) -> ChunkMeshData {
+        mesh_chunk(self, position, self.mesher_settings, |block| registry.is_transparent(block))
+    }
+
+    // Rebuild the mesh for a chunk, spawning, updating or despawning its entity as needed
+    pub fn update_chunk_mesh(
+        &mut self,
+        position: IVec3,
+        commands: &mut Commands,
+        meshes: &mut Assets<Mesh>,
+        registry: &BlockRegistry,
+    ) {
+        let mesh_data = self.build_chunk_mesh(position, registry);
+        self.apply_chunk_mesh(position, mesh_data, commands, meshes);
+    }
+
+    // Show built mesh data for a chunk, spawning, updating or despawning its entity as needed
+    pub fn apply_chunk_mesh(
+        &mut self,
+        position: IVec3,
+        mesh_data: ChunkMeshData,
+        commands: &mut Commands,
+        meshes: &mut Assets<Mesh>,
+    ) {
+        if mesh_data.is_empty() {
+            if let Some(entity) = self.chunk_entities.remove(&position) {
+                commands.entity(entity).despawn();
+            }
+            if let Some(mesh) = self.chunk_meshes.remove(&position) {
+                meshes.remove(&mesh);
+            }
+            return;
+        }
+
+        if let Some(mesh) = self.chunk_meshes.get(&position) {
+            meshes.insert(mesh, mesh_data.into_mesh());
+            return;
+        }
+
+        let chunk_mesh = meshes.add(mesh_data.into_mesh());
+        // Spawn one entity per chunk, offset to the chunk's origin in world space
+        let entity = commands
+            .spawn(MaterialMeshBundle {
+                mesh: chunk_mesh.clone(),
+                material: self.chunk_material.clone(),
+                transform: Transform::from_translation((position * CHUNK_SIZE).as_vec3() * self.voxel_size)
+                    .with_scale(Vec3::splat(self.voxel_size)),
+                ..Default::default()
+            })
+            .id();
+        self.chunk_entities.insert(position, entity);
+        self.chunk_meshes.insert(position, chunk_mesh);
     }
 
     // Generate the voxel terrain
     pub fn generate(
-        &self,
+        &mut self,
         commands: &mut Commands,
-        materials: &mut Assets<StandardMaterial>,
+        materials: &mut Assets<ChunkMaterial>,
         meshes: &mut Assets<Mesh>,
+        registry: &BlockRegistry,
     ) {
-        let half_size = self.size / 2.0;
-        for x in (-half_size.x as i32)..(half_size.x as i32) {
-            for y in (-half_size.y as i32)..(half_size.y as i32) {
-                for z in (-half_size.z as i32)..(half_size.z as i32) {
-                    let voxel_position = Vec3::new(x as f32, y as f32, z as f32) * self.voxel_size;
-                    // Convert the color to a StandardMaterial directly without using into()
-                    let voxel_material = materials.add(StandardMaterial {
-                        base_color: Color::rgb(0.4, 0.7, 0.3),
-                        ..Default::default()
-                    });
-                    // Create a new cuboid mesh with the specified size
-                    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default());
-                    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, vec![[-0.5, -0.5, 0.5], [0.5, -0.5, 0.5], [0.5, 0.5, 0.5], [-0.5, 0.5, 0.5], [-0.5, -0.5, -0.5], [0.5, -0.5, -0.5], [0.5, 0.5, -0.5], [-0.5, 0.5, -0.5]]);
-                    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0.0, 0.0, 1.0], [0.0, 0.0, 1.0], [0.0, 0.0, 1.0], [0.0, 0.0, 1.0], [0.0, 0.0, -1.0], [0.0, 0.0, -1.0], [0.0, 0.0, -1.0], [0.0, 0.0, -1.0]]);
-                    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0], [1.0, 0.0], [0.0, 0.0], [0.0, 1.0], [1.0, 1.0]]);
-                    mesh.insert_indices(Indices::U32(vec![0, 2, 1, 0, 3, 2, 1, 2, 5, 2, 6, 5, 5, 6, 4, 6, 7, 4, 4, 7, 0, 7, 3, 0, 3, 7, 2, 7, 6, 2, 4, 0, 1, 4, 1, 5]));
-                    let voxel_mesh = meshes.add(mesh);
-                    // Spawn the entity with the mesh and material components
-                    commands.spawn(PbrBundle {
-                        mesh: voxel_mesh.clone(),
-                        material: voxel_material.clone(),
-                        transform: Transform::from_translation(voxel_position),
-                        ..Default::default()
-                    });
-
-                    // The generated assets are managed by Bevy's asset system and do not need to be serialized to files
-                    // Removed the code that incorrectly attempted to serialize and write handles to files
-                    // Removed the code that watched the assets directory for changes
-                }
-            }
+        self.fill(registry);
+
+        // Every chunk shares a single material instead of one per voxel; it is textured once the tile sheet loads
+        self.chunk_material = materials.add(ChunkMaterial {
+            base: StandardMaterial::default(),
+            extension: AtlasTiling::default(),
+        });
+
+        let positions: Vec<IVec3> = self.chunks.keys().copied().collect();
+        for position in positions {
+            self.update_chunk_mesh(position, commands, meshes, registry);
         }
+        // Every chunk was just meshed with its final light
+        self.dirty_chunks.clear();
     }
 }
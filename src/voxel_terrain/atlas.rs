@@ -0,0 +1,102 @@
+use bevy::{
+    pbr::{ExtendedMaterial, MaterialExtension},
+    prelude::*,
+    render::render_resource::{AsBindGroup, ShaderRef},
+};
+
+use crate::spritesheet::{Spritesheet, Spritesheets};
+
+use super::{BlockId, BlockRegistry, Face, VoxelTerrain};
+
+// Shader that repeats each block's tile across the greedy quads it is drawn on
+const CHUNK_SHADER: &str = "shaders/chunk.wgsl";
+
+// Where every block's textures sit in the tile sprite sheet
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BlockAtlas {
+    // Size of one tile in normalized sheet coordinates; every Kenney tile is the same size
+    pub tile_size: Vec2,
+    // Normalized corner of each block's top, side and bottom tile, indexed by block id
+    tiles: Vec<[Vec2; 3]>,
+}
+
+impl BlockAtlas {
+    // Look up every registered block's textures in the sheet, logging any the sheet does not have
+    pub fn new(registry: &BlockRegistry, sheet: &Spritesheet) -> Self {
+        let mut atlas = BlockAtlas {
+            tile_size: sheet.rects.first().map_or(Vec2::ZERO, |rect| rect.size() / sheet.size),
+            tiles: Vec::new(),
+        };
+        for block_type in registry.iter() {
+            let index = block_type.id as usize;
+            if atlas.tiles.len() <= index {
+                atlas.tiles.resize(index + 1, [Vec2::ZERO; 3]);
+            }
+            for (slot, face) in [Face::PosY, Face::PosX, Face::NegY].into_iter().enumerate() {
+                let name = block_type.texture(face);
+                if name.is_empty() {
+                    continue;
+                }
+                match sheet.uv_rect(name) {
+                    Some(rect) => atlas.tiles[index][slot] = rect.min,
+                    None => warn!("Block '{}' uses texture {} which is not in the tile sheet", block_type.name, name),
+                }
+            }
+        }
+        atlas
+    }
+
+    // Normalized corner of the tile drawn on the given side of a block
+    pub fn tile(&self, block: BlockId, face: Face) -> Vec2 {
+        let slot = match face {
+            Face::PosY => 0,
+            Face::NegY => 2,
+            _ => 1,
+        };
+        self.tiles.get(block as usize).map_or(Vec2::ZERO, |tiles| tiles[slot])
+    }
+}
+
+// Material extension telling the chunk shader how big a tile is in the sheet
+#[derive(Asset, AsBindGroup, TypePath, Debug, Clone, Default)]
+pub struct AtlasTiling {
+    #[uniform(100)]
+    pub tile_size: Vec2,
+}
+
+impl MaterialExtension for AtlasTiling {
+    fn fragment_shader() -> ShaderRef {
+        CHUNK_SHADER.into()
+    }
+
+    fn deferred_fragment_shader() -> ShaderRef {
+        CHUNK_SHADER.into()
+    }
+}
+
+// Material every chunk is drawn with: the standard material textured from the tile sheet
+pub type ChunkMaterial = ExtendedMaterial<StandardMaterial, AtlasTiling>;
+
+// System to texture the chunks once the tile sheet has loaded, remeshing them with their blocks' tiles
+pub fn apply_block_atlas(
+    mut terrain: ResMut<VoxelTerrain>,
+    registry: Res<BlockRegistry>,
+    spritesheets: Option<Res<Spritesheets>>,
+    sheets: Res<Assets<Spritesheet>>,
+    mut materials: ResMut<Assets<ChunkMaterial>>,
+) {
+    if terrain.block_atlas.is_some() {
+        return;
+    }
+    let Some(sheet) = spritesheets.and_then(|spritesheets| sheets.get(&spritesheets.tiles)) else {
+        return;
+    };
+    let atlas = BlockAtlas::new(&registry, sheet);
+    if let Some(material) = materials.get_mut(&terrain.chunk_material) {
+        material.base.base_color_texture = Some(sheet.image.clone());
+        material.extension.tile_size = atlas.tile_size;
+    }
+    terrain.block_atlas = Some(atlas.into());
+    let loaded: Vec<IVec3> = terrain.chunks.keys().copied().collect();
+    terrain.dirty_chunks.extend(loaded);
+}
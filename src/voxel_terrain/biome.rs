@@ -0,0 +1,224 @@
+use bevy::prelude::*;
+
+use super::{Decoration, DecorationSpawn, Noise};
+
+// Noise layer salts for the climate maps, distinct from the ones used by terrain generation
+const TEMPERATURE_LAYER: u64 = 4;
+const HUMIDITY_LAYER: u64 = 5;
+
+// Scale applied to climate noise so values spread over most of -1..1
+const CLIMATE_CONTRAST: f32 = 2.5;
+
+// An enemy kind that can spawn in a biome, weighted against the other entries
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnemySpawn {
+    pub enemy: String,
+    pub weight: u32,
+}
+
+// A region of the world with its own climate, blocks, vegetation and enemies
+#[derive(Debug, Clone, PartialEq)]
+pub struct Biome {
+    pub name: String,
+    // Climate the biome is centred on, each in -1..1
+    pub temperature: f32,
+    pub humidity: f32,
+    pub surface_block: String,
+    pub filler_block: String,
+    // Chance per surface column of placing vegetation, from 0 to 1
+    pub vegetation_density: f32,
+    // Decorations chosen from when a column grows vegetation
+    pub decorations: Vec<DecorationSpawn>,
+    pub enemy_spawns: Vec<EnemySpawn>,
+}
+
+impl Biome {
+    pub fn new(name: &str, temperature: f32, humidity: f32, surface_block: &str, filler_block: &str) -> Self {
+        Biome {
+            name: name.to_string(),
+            temperature,
+            humidity,
+            surface_block: surface_block.to_string(),
+            filler_block: filler_block.to_string(),
+            vegetation_density: 0.0,
+            decorations: Vec::new(),
+            enemy_spawns: Vec::new(),
+        }
+    }
+
+    pub fn with_vegetation(mut self, vegetation_density: f32) -> Self {
+        self.vegetation_density = vegetation_density;
+        self
+    }
+
+    pub fn with_decoration(mut self, decoration: Decoration, weight: u32) -> Self {
+        self.decorations.push(DecorationSpawn { decoration, weight });
+        self
+    }
+
+    pub fn with_enemy(mut self, enemy: &str, weight: u32) -> Self {
+        self.enemy_spawns.push(EnemySpawn {
+            enemy: enemy.to_string(),
+            weight,
+        });
+        self
+    }
+
+    // Pick an enemy from the spawn table using a roll in 0..1
+    pub fn pick_enemy(&self, roll: f32) -> Option<&str> {
+        let total: u32 = self.enemy_spawns.iter().map(|spawn| spawn.weight).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut remaining = ((roll.clamp(0.0, 1.0) * total as f32) as u32).min(total - 1);
+        for spawn in &self.enemy_spawns {
+            if remaining < spawn.weight {
+                return Some(&spawn.enemy);
+            }
+            remaining -= spawn.weight;
+        }
+        None
+    }
+}
+
+// Biomes used when no custom table is configured
+pub fn default_biomes() -> Vec<Biome> {
+    vec![
+        Biome::new("plains", 0.0, 0.0, "grass", "dirt")
+            .with_vegetation(0.03)
+            .with_decoration(Decoration::plant("tall_grass"), 10)
+            .with_decoration(Decoration::plant("wheat"), 2)
+            .with_decoration(Decoration::tree("trunk", "leaves", 4, 6), 1)
+            .with_enemy("slime", 10)
+            .with_enemy("goblin", 5),
+        Biome::new("forest", 0.1, 0.6, "grass", "dirt")
+            .with_vegetation(0.12)
+            .with_decoration(Decoration::tree("trunk", "leaves", 4, 7), 6)
+            .with_decoration(Decoration::tree("trunk_white", "leaves_orange", 5, 7), 2)
+            .with_decoration(Decoration::plant("tall_grass"), 6)
+            .with_decoration(Decoration::plant("mushroom_brown"), 1)
+            .with_decoration(Decoration::plant("mushroom_red"), 1)
+            .with_enemy("goblin", 8)
+            .with_enemy("wolf", 6)
+            .with_enemy("spider", 4),
+        Biome::new("savanna", 0.5, -0.2, "grass_brown", "dirt")
+            .with_vegetation(0.02)
+            .with_decoration(Decoration::plant("tall_grass"), 6)
+            .with_decoration(Decoration::tree("trunk", "leaves_orange", 4, 5), 2)
+            .with_enemy("hyena", 6)
+            .with_enemy("goblin", 4),
+        Biome::new("steppe", -0.3, -0.5, "grass_tan", "dirt")
+            .with_vegetation(0.02)
+            .with_decoration(Decoration::plant("tall_grass"), 1)
+            .with_enemy("wolf", 6)
+            .with_enemy("bandit", 3),
+        Biome::new("desert", 0.8, -0.7, "sand", "sand")
+            .with_vegetation(0.01)
+            .with_decoration(Decoration::cactus("cactus", 3), 1)
+            .with_enemy("scorpion", 8)
+            .with_enemy("mummy", 3),
+        Biome::new("badlands", 0.9, 0.1, "redsand", "redsand")
+            .with_vegetation(0.005)
+            .with_decoration(Decoration::cactus("cactus", 2), 1)
+            .with_enemy("fire_imp", 6)
+            .with_enemy("scorpion", 4),
+        Biome::new("tundra", -0.8, 0.0, "dirt_snow", "dirt")
+            .with_vegetation(0.02)
+            .with_decoration(Decoration::tree("trunk_white", "leaves", 5, 8), 2)
+            .with_decoration(Decoration::plant("mushroom_brown"), 1)
+            .with_enemy("ice_wolf", 6)
+            .with_enemy("yeti", 2),
+        Biome::new("glacier", -0.9, 0.7, "ice", "snow")
+            .with_enemy("yeti", 4)
+            .with_enemy("frost_wraith", 3),
+    ]
+}
+
+// Temperature and humidity maps sampled per world column
+#[derive(Debug, Clone, Copy)]
+pub struct Climate {
+    temperature: Noise,
+    humidity: Noise,
+    frequency: f32,
+}
+
+impl Climate {
+    pub fn new(seed: u64, frequency: f32) -> Self {
+        let noise = Noise::new(seed);
+        Climate {
+            temperature: noise.layer(TEMPERATURE_LAYER),
+            humidity: noise.layer(HUMIDITY_LAYER),
+            frequency,
+        }
+    }
+
+    // Temperature and humidity of the column at a world x/z coordinate, each in -1..1
+    pub fn sample(&self, x: i32, z: i32) -> (f32, f32) {
+        let position = Vec2::new(x as f32, z as f32) * self.frequency;
+        let temperature = (self.temperature.fbm2(position, 3) * CLIMATE_CONTRAST).clamp(-1.0, 1.0);
+        let humidity = (self.humidity.fbm2(position, 3) * CLIMATE_CONTRAST).clamp(-1.0, 1.0);
+        (temperature, humidity)
+    }
+}
+
+// Index of the biome whose climate is closest to the given temperature and humidity
+pub fn select_biome(biomes: &[Biome], temperature: f32, humidity: f32) -> Option<usize> {
+    let climate = Vec2::new(temperature, humidity);
+    biomes
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| {
+            let distance_a = Vec2::new(a.temperature, a.humidity).distance_squared(climate);
+            let distance_b = Vec2::new(b.temperature, b.humidity).distance_squared(climate);
+            distance_a.total_cmp(&distance_b)
+        })
+        .map(|(index, _)| index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel_terrain::{test_registry, world_to_chunk, VoxelTerrain};
+
+    fn biome_name(biomes: &[Biome], temperature: f32, humidity: f32) -> &str {
+        &biomes[select_biome(biomes, temperature, humidity).expect("some biome is selected")].name
+    }
+
+    #[test]
+    fn climates_map_to_expected_biomes() {
+        let biomes = default_biomes();
+        // Every biome owns the climate it is centred on
+        for biome in &biomes {
+            assert_eq!(biome_name(&biomes, biome.temperature, biome.humidity), biome.name);
+        }
+        assert_eq!(biome_name(&biomes, 1.0, -1.0), "desert");
+        assert_eq!(biome_name(&biomes, 1.0, 0.3), "badlands");
+        assert_eq!(biome_name(&biomes, -1.0, 1.0), "glacier");
+        assert_eq!(biome_name(&biomes, -1.0, -0.1), "tundra");
+        assert_eq!(biome_name(&biomes, 0.0, 1.0), "forest");
+        assert_eq!(biome_name(&biomes, 0.05, -0.05), "plains");
+        assert_eq!(select_biome(&[], 0.0, 0.0), None);
+    }
+
+    #[test]
+    fn biome_at_matches_generated_surface() {
+        let registry = test_registry();
+        let mut terrain = VoxelTerrain::new(Vec3::ZERO, 0.5).with_seed(11);
+        let generator = terrain.generator(&registry);
+        let mut seen = Vec::new();
+        for x in (-2000..2000).step_by(193) {
+            for z in (-2000..2000).step_by(181) {
+                // Sample the middle of the block so rounding cannot land in a neighbouring column
+                let position = Vec3::new(x as f32 + 0.5, 0.0, z as f32 + 0.5) * terrain.voxel_size;
+                let biome = terrain.biome_at(position).expect("default settings have biomes");
+                let (chunk, local) = world_to_chunk(IVec3::new(x, generator.height_at(x, z), z));
+                let surface = generator.generate_chunk(chunk).get(local);
+                assert_eq!(Some(surface), registry.id(&biome.surface_block), "biome {} at {}, {}", biome.name, x, z);
+                if !seen.contains(&biome.name) {
+                    seen.push(biome.name.clone());
+                }
+            }
+        }
+        assert!(seen.len() >= 3, "only saw biomes {:?}", seen);
+    }
+}
@@ -0,0 +1,292 @@
+use std::{collections::HashMap, fmt, fs, path::Path};
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use super::{BlockId, Face, AIR, MAX_LIGHT};
+
+// Texture names (matching the files in assets/PNG/Tiles) used for each side of a block
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct BlockTextures {
+    pub top: String,
+    pub side: String,
+    pub bottom: String,
+}
+
+// How a fluid block flows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct FluidProperties {
+    // Number of blocks the fluid spreads sideways from a source
+    pub flow_distance: u8,
+    // Fixed ticks between flow steps; higher values make the fluid slower
+    pub ticks_per_step: u32,
+}
+
+impl FluidProperties {
+    // Level of a source block; flowing blocks lose one level per block travelled
+    pub fn source_level(&self) -> u8 {
+        self.flow_distance + 1
+    }
+}
+
+// Definition of a single block type as written in the registry file
+#[derive(Debug, Clone, Deserialize)]
+pub struct BlockType {
+    pub id: BlockId,
+    pub name: String,
+    #[serde(default)]
+    pub textures: BlockTextures,
+    // Whether entities collide with the block
+    #[serde(default = "default_solid")]
+    pub solid: bool,
+    // Whether faces behind the block stay visible
+    #[serde(default)]
+    pub transparent: bool,
+    // Light level emitted by the block, from 0 (none) to 15
+    #[serde(default)]
+    pub emissive: u8,
+    // Time in seconds needed to break the block; negative values are unbreakable
+    #[serde(default = "default_hardness")]
+    pub hardness: f32,
+    // Set for blocks that flow, such as water and lava
+    #[serde(default)]
+    pub fluid: Option<FluidProperties>,
+    // Damage per second dealt to entities inside the block
+    #[serde(default)]
+    pub contact_damage: u32,
+    // Whether the block falls when there is nothing solid under it, like sand
+    #[serde(default)]
+    pub falls: bool,
+}
+
+fn default_solid() -> bool {
+    true
+}
+
+fn default_hardness() -> f32 {
+    1.0
+}
+
+impl BlockType {
+    // The block type used for empty space
+    fn air() -> Self {
+        BlockType {
+            id: AIR,
+            name: "air".to_string(),
+            textures: BlockTextures::default(),
+            solid: false,
+            transparent: true,
+            emissive: 0,
+            hardness: 0.0,
+            fluid: None,
+            contact_damage: 0,
+            falls: false,
+        }
+    }
+
+    // Texture name for the given side of the block
+    pub fn texture(&self, face: Face) -> &str {
+        match face {
+            Face::PosY => &self.textures.top,
+            Face::NegY => &self.textures.bottom,
+            _ => &self.textures.side,
+        }
+    }
+}
+
+// Errors that can occur while loading the block registry
+#[derive(Debug)]
+pub enum BlockRegistryError {
+    Io(std::io::Error),
+    Parse(ron::Error),
+    ReservedId(String),
+    DuplicateId(BlockId),
+    DuplicateName(String),
+    // A block emits more light than the lighting engine can store
+    InvalidEmission(String, u8),
+}
+
+impl fmt::Display for BlockRegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockRegistryError::Io(error) => write!(f, "could not read block registry: {}", error),
+            BlockRegistryError::Parse(error) => write!(f, "could not parse block registry: {}", error),
+            BlockRegistryError::ReservedId(name) => write!(f, "block '{}' uses the id reserved for air", name),
+            BlockRegistryError::DuplicateId(id) => write!(f, "block id {} is defined more than once", id),
+            BlockRegistryError::DuplicateName(name) => write!(f, "block name '{}' is defined more than once", name),
+            BlockRegistryError::InvalidEmission(name, level) => {
+                write!(f, "block '{}' emits light level {}, above the maximum of {}", name, level, MAX_LIGHT)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BlockRegistryError {}
+
+// All block types known to the game, indexed by block id
+#[derive(Resource, Debug, Clone)]
+pub struct BlockRegistry {
+    types: Vec<Option<BlockType>>,
+    names: HashMap<String, BlockId>,
+}
+
+impl Default for BlockRegistry {
+    fn default() -> Self {
+        BlockRegistry {
+            types: vec![Some(BlockType::air())],
+            names: HashMap::from([("air".to_string(), AIR)]),
+        }
+    }
+}
+
+impl BlockRegistry {
+    // Build a registry from a list of block types, validating ids and names
+    pub fn from_types(types: Vec<BlockType>) -> Result<Self, BlockRegistryError> {
+        let mut registry = BlockRegistry::default();
+        for block_type in types {
+            registry.register(block_type)?;
+        }
+        Ok(registry)
+    }
+
+    // Parse a registry from RON text holding a list of block types
+    pub fn from_ron(source: &str) -> Result<Self, BlockRegistryError> {
+        let types: Vec<BlockType> = ron::de::from_str(source).map_err(BlockRegistryError::Parse)?;
+        BlockRegistry::from_types(types)
+    }
+
+    // Load a registry from a RON file on disk
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, BlockRegistryError> {
+        let source = fs::read_to_string(path).map_err(BlockRegistryError::Io)?;
+        BlockRegistry::from_ron(&source)
+    }
+
+    // Add a block type to the registry
+    pub fn register(&mut self, block_type: BlockType) -> Result<(), BlockRegistryError> {
+        if block_type.id == AIR {
+            return Err(BlockRegistryError::ReservedId(block_type.name));
+        }
+        if self.get(block_type.id).is_some() {
+            return Err(BlockRegistryError::DuplicateId(block_type.id));
+        }
+        if self.names.contains_key(&block_type.name) {
+            return Err(BlockRegistryError::DuplicateName(block_type.name));
+        }
+        if block_type.emissive > MAX_LIGHT {
+            return Err(BlockRegistryError::InvalidEmission(block_type.name, block_type.emissive));
+        }
+
+        let index = block_type.id as usize;
+        if self.types.len() <= index {
+            self.types.resize(index + 1, None);
+        }
+        self.names.insert(block_type.name.clone(), block_type.id);
+        self.types[index] = Some(block_type);
+        Ok(())
+    }
+
+    // Look up a block type by id
+    pub fn get(&self, id: BlockId) -> Option<&BlockType> {
+        self.types.get(id as usize).and_then(Option::as_ref)
+    }
+
+    // Look up a block id by name
+    pub fn id(&self, name: &str) -> Option<BlockId> {
+        self.names.get(name).copied()
+    }
+
+    // Look up a block type by name
+    pub fn by_name(&self, name: &str) -> Option<&BlockType> {
+        self.id(name).and_then(|id| self.get(id))
+    }
+
+    // Iterate over every registered block type, including air
+    pub fn iter(&self) -> impl Iterator<Item = &BlockType> {
+        self.types.iter().flatten()
+    }
+
+    // Whether entities collide with the block; unknown ids are treated as solid
+    pub fn is_solid(&self, id: BlockId) -> bool {
+        self.get(id).is_none_or(|block_type| block_type.solid)
+    }
+
+    // Whether faces behind the block stay visible; unknown ids are treated as opaque
+    pub fn is_transparent(&self, id: BlockId) -> bool {
+        self.get(id).is_some_and(|block_type| block_type.transparent)
+    }
+
+    // Light level emitted by the block
+    pub fn emission(&self, id: BlockId) -> u8 {
+        self.get(id).map_or(0, |block_type| block_type.emissive)
+    }
+
+    // Time in seconds needed to break the block
+    pub fn hardness(&self, id: BlockId) -> f32 {
+        self.get(id).map_or(1.0, |block_type| block_type.hardness)
+    }
+
+    // How the block flows, if it is a fluid
+    pub fn fluid(&self, id: BlockId) -> Option<FluidProperties> {
+        self.get(id).and_then(|block_type| block_type.fluid)
+    }
+
+    // Damage per second dealt to entities inside the block
+    pub fn contact_damage(&self, id: BlockId) -> u32 {
+        self.get(id).map_or(0, |block_type| block_type.contact_damage)
+    }
+
+    // Whether the block falls when there is nothing solid under it
+    pub fn falls(&self, id: BlockId) -> bool {
+        self.get(id).is_some_and(|block_type| block_type.falls)
+    }
+}
+
+// Registry loaded from the game's block file, for tests that need real block types
+#[cfg(test)]
+pub(crate) fn test_registry() -> BlockRegistry {
+    BlockRegistry::load(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/blocks.ron")).expect("failed to load block registry")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_the_game_registry() {
+        let registry = test_registry();
+        let stone = registry.id("stone").expect("stone is registered");
+        assert!(registry.is_solid(stone));
+        assert!(!registry.is_solid(AIR));
+        assert!(registry.is_transparent(AIR));
+        // Unknown ids are solid and opaque so bad data never lets entities fall out of the world
+        assert!(registry.is_solid(BlockId::MAX));
+        assert!(!registry.is_transparent(BlockId::MAX));
+        assert!(registry.iter().all(|block_type| block_type.emissive <= MAX_LIGHT));
+    }
+
+    #[test]
+    fn rejects_duplicate_names() {
+        let result = BlockRegistry::from_ron(r#"[(id: 1, name: "stone"), (id: 2, name: "stone")]"#);
+        assert!(matches!(result, Err(BlockRegistryError::DuplicateName(name)) if name == "stone"));
+        // Air is registered before any file is read
+        let result = BlockRegistry::from_ron(r#"[(id: 1, name: "air")]"#);
+        assert!(matches!(result, Err(BlockRegistryError::DuplicateName(name)) if name == "air"));
+    }
+
+    #[test]
+    fn rejects_duplicate_and_reserved_ids() {
+        let result = BlockRegistry::from_ron(r#"[(id: 1, name: "stone"), (id: 1, name: "dirt")]"#);
+        assert!(matches!(result, Err(BlockRegistryError::DuplicateId(1))));
+        let result = BlockRegistry::from_ron(r#"[(id: 0, name: "void")]"#);
+        assert!(matches!(result, Err(BlockRegistryError::ReservedId(name)) if name == "void"));
+    }
+
+    #[test]
+    fn rejects_emission_above_max_light() {
+        let source = format!(r#"[(id: 1, name: "lamp", emissive: {})]"#, MAX_LIGHT);
+        assert_eq!(BlockRegistry::from_ron(&source).expect("brightest light is valid").emission(1), MAX_LIGHT);
+        let source = format!(r#"[(id: 1, name: "lamp", emissive: {})]"#, MAX_LIGHT + 1);
+        let result = BlockRegistry::from_ron(&source);
+        assert!(matches!(result, Err(BlockRegistryError::InvalidEmission(name, level)) if name == "lamp" && level == MAX_LIGHT + 1));
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+
+// Numeric identifier of a block type stored in chunk data
+pub type BlockId = u16;
+
+// Block id reserved for empty space
+pub const AIR: BlockId = 0;
+
+// Number of blocks along each edge of a chunk
+pub const CHUNK_SIZE: i32 = 16;
+
+// Number of blocks stored in a single chunk
+pub const CHUNK_VOLUME: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+// A fixed-size cube of blocks stored as a dense array
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    blocks: Vec<BlockId>,
+    // Levels of flowing fluid blocks by block index; fluid blocks without an entry are sources
+    fluid_levels: BTreeMap<u16, u8>,
+}
+
+impl Default for Chunk {
+    fn default() -> Self {
+        Chunk::filled(AIR)
+    }
+}
+
+impl Chunk {
+    // Create a chunk with every block set to the given block id
+    pub fn filled(block: BlockId) -> Self {
+        Chunk {
+            blocks: vec![block; CHUNK_VOLUME],
+            fluid_levels: BTreeMap::new(),
+        }
+    }
+
+    // Create a chunk from a block array in index order, which must hold exactly `CHUNK_VOLUME` blocks
+    pub fn from_blocks(blocks: Vec<BlockId>) -> Option<Self> {
+        (blocks.len() == CHUNK_VOLUME).then_some(Chunk {
+            blocks,
+            fluid_levels: BTreeMap::new(),
+        })
+    }
+
+    // Convert a local block coordinate into an index into the block array
+    pub fn index(local: UVec3) -> usize {
+        let size = CHUNK_SIZE as usize;
+        local.x as usize + local.z as usize * size + local.y as usize * size * size
+    }
+
+    // Convert an index into the block array back into a local block coordinate
+    pub fn local_from_index(index: usize) -> UVec3 {
+        let size = CHUNK_SIZE as usize;
+        UVec3::new((index % size) as u32, (index / (size * size)) as u32, ((index / size) % size) as u32)
+    }
+
+    // Check whether a signed local coordinate lies inside the chunk
+    pub fn contains(local: IVec3) -> bool {
+        local.cmpge(IVec3::ZERO).all() && local.cmplt(IVec3::splat(CHUNK_SIZE)).all()
+    }
+
+    // Read the block at a local coordinate
+    pub fn get(&self, local: UVec3) -> BlockId {
+        self.blocks[Chunk::index(local)]
+    }
+
+    // Write the block at a local coordinate, returning the block that was replaced.
+    // A different block clears the fluid level, so placed fluid starts out as a source.
+    pub fn set(&mut self, local: UVec3, block: BlockId) -> BlockId {
+        let index = Chunk::index(local);
+        let previous = std::mem::replace(&mut self.blocks[index], block);
+        if previous != block {
+            self.fluid_levels.remove(&(index as u16));
+        }
+        previous
+    }
+
+    // Level of the flowing fluid at a local coordinate, or None for sources and blocks that are not fluids
+    pub fn fluid_level(&self, local: UVec3) -> Option<u8> {
+        self.fluid_levels.get(&(Chunk::index(local) as u16)).copied()
+    }
+
+    // Set the level of the flowing fluid at a local coordinate, or make it a source with None
+    pub fn set_fluid_level(&mut self, local: UVec3, level: Option<u8>) {
+        let index = Chunk::index(local) as u16;
+        match level {
+            Some(level) => self.fluid_levels.insert(index, level),
+            None => self.fluid_levels.remove(&index),
+        };
+    }
+
+    // Flowing fluid levels keyed by block index, in index order
+    pub fn fluid_levels(&self) -> impl Iterator<Item = (usize, u8)> + '_ {
+        self.fluid_levels.iter().map(|(&index, &level)| (index as usize, level))
+    }
+
+    // Check whether the chunk contains only air
+    pub fn is_empty(&self) -> bool {
+        self.blocks.iter().all(|&block| block == AIR)
+    }
+
+    // Access the raw block array in index order
+    pub fn blocks(&self) -> &[BlockId] {
+        &self.blocks
+    }
+}
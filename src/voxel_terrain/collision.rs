@@ -0,0 +1,61 @@
+use bevy::prelude::*;
+
+use super::{BlockId, VoxelTerrain};
+
+// Gap ignored when testing boxes against blocks, so a box resting exactly on a face does not count as overlapping it
+const COLLISION_EPSILON: f32 = 1e-4;
+
+impl VoxelTerrain {
+    // Range of block coordinates a box spans along one axis, in block units
+    fn box_cells(min: f32, max: f32) -> std::ops::RangeInclusive<i32> {
+        (min + COLLISION_EPSILON).floor() as i32..=(max - COLLISION_EPSILON).ceil() as i32 - 1
+    }
+
+    // Whether a box in world space overlaps any block accepted by `is_solid`
+    pub fn box_overlaps(&self, min: Vec3, max: Vec3, is_solid: impl Fn(BlockId) -> bool) -> bool {
+        let (min, max) = (min / self.voxel_size, max / self.voxel_size);
+        Self::box_cells(min.x, max.x).any(|x| {
+            Self::box_cells(min.y, max.y)
+                .any(|y| Self::box_cells(min.z, max.z).any(|z| is_solid(self.get_block(IVec3::new(x, y, z)))))
+        })
+    }
+
+    // Move a box in world space along one axis (0, 1 or 2) until it touches a block accepted by `is_solid`.
+    // Returns how far the box can move, which is `distance` when nothing is in the way.
+    pub fn sweep_box(&self, min: Vec3, max: Vec3, axis: usize, distance: f32, is_solid: impl Fn(BlockId) -> bool) -> f32 {
+        if distance == 0.0 {
+            return 0.0;
+        }
+        let (min, max) = (min / self.voxel_size, max / self.voxel_size);
+        let distance = distance / self.voxel_size;
+        let (u_axis, v_axis) = ((axis + 1) % 3, (axis + 2) % 3);
+        let layer_blocked = |layer: i32| {
+            Self::box_cells(min[u_axis], max[u_axis]).any(|u| {
+                Self::box_cells(min[v_axis], max[v_axis]).any(|v| {
+                    let mut block = IVec3::ZERO;
+                    block[axis] = layer;
+                    block[u_axis] = u;
+                    block[v_axis] = v;
+                    is_solid(self.get_block(block))
+                })
+            })
+        };
+
+        // Check each layer of blocks the leading face passes into, nearest first
+        let allowed = if distance > 0.0 {
+            let first = (max[axis] - COLLISION_EPSILON).ceil() as i32;
+            let last = (max[axis] + distance - COLLISION_EPSILON).ceil() as i32 - 1;
+            (first..=last)
+                .find(|&layer| layer_blocked(layer))
+                .map_or(distance, |layer| (layer as f32 - max[axis]).clamp(0.0, distance))
+        } else {
+            let first = (min[axis] + COLLISION_EPSILON).floor() as i32 - 1;
+            let last = (min[axis] + distance + COLLISION_EPSILON).floor() as i32;
+            (last..=first)
+                .rev()
+                .find(|&layer| layer_blocked(layer))
+                .map_or(distance, |layer| (layer as f32 + 1.0 - min[axis]).clamp(distance, 0.0))
+        };
+        allowed * self.voxel_size
+    }
+}
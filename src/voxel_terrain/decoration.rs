@@ -0,0 +1,151 @@
+use bevy::prelude::*;
+
+use super::{chunk_to_world, BlockId, BlockRegistry, Chunk, Noise, AIR, CHUNK_SIZE};
+
+// Furthest a decoration reaches sideways from the column it grows out of
+pub const MAX_DECORATION_RADIUS: i32 = 2;
+
+// Furthest a decoration reaches above the column it grows out of
+pub const MAX_DECORATION_HEIGHT: i32 = 10;
+
+// A structure placed on top of the generated surface
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decoration {
+    // A trunk topped by a rounded crown of leaves
+    Tree {
+        trunk: String,
+        leaves: String,
+        min_height: i32,
+        max_height: i32,
+    },
+    // A column of blocks between one block and `max_height` tall
+    Cactus { block: String, max_height: i32 },
+    // A single block resting on the surface, such as grass, flowers or mushrooms
+    Plant { block: String },
+}
+
+impl Decoration {
+    pub fn tree(trunk: &str, leaves: &str, min_height: i32, max_height: i32) -> Self {
+        Decoration::Tree {
+            trunk: trunk.to_string(),
+            leaves: leaves.to_string(),
+            min_height,
+            max_height,
+        }
+    }
+
+    pub fn cactus(block: &str, max_height: i32) -> Self {
+        Decoration::Cactus {
+            block: block.to_string(),
+            max_height,
+        }
+    }
+
+    pub fn plant(block: &str) -> Self {
+        Decoration::Plant { block: block.to_string() }
+    }
+}
+
+// A decoration that can grow in a biome, weighted against the other entries
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecorationSpawn {
+    pub decoration: Decoration,
+    pub weight: u32,
+}
+
+// A decoration with its block names resolved to ids
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum PlacedDecoration {
+    Tree {
+        trunk: BlockId,
+        leaves: BlockId,
+        min_height: i32,
+        max_height: i32,
+    },
+    Cactus { block: BlockId, max_height: i32 },
+    Plant { block: BlockId },
+}
+
+impl PlacedDecoration {
+    // Resolve block names, skipping decorations whose blocks the registry does not define
+    pub(super) fn resolve(decoration: &Decoration, registry: &BlockRegistry) -> Option<Self> {
+        let id = |name: &str| {
+            let id = registry.id(name);
+            if id.is_none() {
+                warn!("Block registry has no '{}' block, skipping decorations that use it", name);
+            }
+            id
+        };
+        Some(match decoration {
+            Decoration::Tree { trunk, leaves, min_height, max_height } => PlacedDecoration::Tree {
+                trunk: id(trunk)?,
+                leaves: id(leaves)?,
+                min_height: (*min_height).clamp(1, MAX_DECORATION_HEIGHT - 2),
+                max_height: (*max_height).clamp(*min_height, MAX_DECORATION_HEIGHT - 2),
+            },
+            Decoration::Cactus { block, max_height } => PlacedDecoration::Cactus {
+                block: id(block)?,
+                max_height: (*max_height).clamp(1, MAX_DECORATION_HEIGHT),
+            },
+            Decoration::Plant { block } => PlacedDecoration::Plant { block: id(block)? },
+        })
+    }
+
+    // Produce the blocks of the decoration grown at `anchor`, the first air block above the surface.
+    // `place` receives each block and whether it may replace non-air blocks.
+    pub(super) fn place(&self, anchor: IVec3, noise: &Noise, mut place: impl FnMut(IVec3, BlockId, bool)) {
+        // Random value in 0..`count` picked from the anchor, so every chunk makes the same choice
+        let roll = |salt: i32, count: i32| ((noise.random(anchor + IVec3::new(0, salt, 0)) * count as f32) as i32).min(count - 1);
+
+        match *self {
+            PlacedDecoration::Tree { trunk, leaves, min_height, max_height } => {
+                let height = min_height + roll(1, max_height - min_height + 1);
+                let top = anchor + IVec3::new(0, height - 1, 0);
+                // Two wide layers around the top of the trunk and two narrow ones above them
+                for dy in -2..=1 {
+                    let radius = if dy < 0 { 2 } else { 1 };
+                    for dx in -radius..=radius {
+                        for dz in -radius..=radius {
+                            let offset = IVec3::new(dx, dy, dz);
+                            let corner = dx.abs() == radius && dz.abs() == radius;
+                            if corner && (dy == 1 || noise.random(top + offset) < 0.5) {
+                                continue;
+                            }
+                            place(top + offset, leaves, false);
+                        }
+                    }
+                }
+                for dy in 0..height {
+                    place(anchor + IVec3::new(0, dy, 0), trunk, true);
+                }
+            }
+            PlacedDecoration::Cactus { block, max_height } => {
+                for dy in 0..=roll(1, max_height) {
+                    place(anchor + IVec3::new(0, dy, 0), block, true);
+                }
+            }
+            PlacedDecoration::Plant { block } => place(anchor, block, false),
+        }
+    }
+}
+
+// Write the parts of a decoration that fall inside the chunk at `position`
+pub(super) fn place_in_chunk(chunk: &mut Chunk, position: IVec3, decoration: &PlacedDecoration, anchor: IVec3, noise: &Noise) {
+    let origin = chunk_to_world(position, UVec3::ZERO);
+    decoration.place(anchor, noise, |world, block, replace| {
+        let local = world - origin;
+        if !Chunk::contains(local) {
+            return;
+        }
+        let local = local.as_uvec3();
+        if replace || chunk.get(local) == AIR {
+            chunk.set(local, block);
+        }
+    });
+}
+
+// Whether a decoration anchored at `anchor_y` could reach into a chunk at chunk height `chunk_y`
+pub(super) fn may_reach_chunk(anchor_y: i32, chunk_y: i32) -> bool {
+    let bottom = chunk_y * CHUNK_SIZE;
+    anchor_y < bottom + CHUNK_SIZE && anchor_y + MAX_DECORATION_HEIGHT > bottom
+}
@@ -0,0 +1,582 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet, VecDeque},
+    fmt,
+};
+
+use bevy::prelude::*;
+
+use super::{BlockId, BlockRegistry, MarkerKind, Noise, Rotation, Structure, StructureError, VoxelTerrain, AIR};
+
+// Noise layer salt for dungeon layouts, distinct from the ones used by terrain generation and climate
+const DUNGEON_LAYER: u64 = 7;
+
+// Blocks of clear space kept between the walls of neighbouring rooms
+const ROOM_MARGIN: i32 = 2;
+
+// Attempts made per requested room before giving up on fitting more
+const PLACEMENT_ATTEMPTS: usize = 30;
+
+// Size, density and blocks of generated dungeons
+#[derive(Debug, Clone, PartialEq)]
+pub struct DungeonSettings {
+    pub wall_block: String,
+    pub floor_block: String,
+    pub door_block: String,
+    pub chest_block: String,
+    // Number of rooms to place; fewer are placed if they do not fit in the area
+    pub room_count: usize,
+    // Smallest and largest room interior along x and z
+    pub min_room_size: i32,
+    pub max_room_size: i32,
+    // Interior height of rooms and corridors
+    pub room_height: i32,
+    pub corridor_height: i32,
+    // Half the width of the square area rooms are placed in, around the dungeon origin
+    pub extent: i32,
+    // Chance of joining a room to its nearest unconnected room as well, which adds loops to the layout
+    pub loop_chance: f32,
+    // Chance of a chest in each room other than the entrance; the boss room always has one unless a structure furnishes it
+    pub chest_chance: f32,
+    // Most enemy spawns in each room other than the entrance
+    pub max_enemies_per_room: i32,
+    // Hand-authored rooms that may be placed in rooms large enough to hold them
+    pub structures: Vec<Structure>,
+    // Chance of furnishing a room other than the entrance with one of the structures
+    pub structure_chance: f32,
+}
+
+impl Default for DungeonSettings {
+    fn default() -> Self {
+        DungeonSettings {
+            wall_block: "brick_grey".to_string(),
+            floor_block: "greystone".to_string(),
+            door_block: "door".to_string(),
+            chest_block: "chest".to_string(),
+            room_count: 8,
+            min_room_size: 5,
+            max_room_size: 11,
+            room_height: 4,
+            corridor_height: 3,
+            extent: 40,
+            loop_chance: 0.2,
+            chest_chance: 0.5,
+            max_enemies_per_room: 3,
+            structures: Vec::new(),
+            structure_chance: 0.3,
+        }
+    }
+}
+
+// Errors that can occur while carving a dungeon
+#[derive(Debug)]
+pub enum DungeonError {
+    UnknownBlock(String),
+    Structure(StructureError),
+}
+
+impl fmt::Display for DungeonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DungeonError::UnknownBlock(name) => write!(f, "block registry has no '{}' block for dungeons", name),
+            DungeonError::Structure(error) => write!(f, "could not place dungeon structure: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for DungeonError {}
+
+// Role of a room in the dungeon graph
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RoomKind {
+    // Where the player enters; it has no enemies or chests
+    Entrance,
+    Chamber,
+    // The room furthest from the entrance
+    Boss,
+}
+
+// A room of a dungeon, with the things placed in it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DungeonRoom {
+    pub kind: RoomKind,
+    // Inclusive corners of the open interior in world block coordinates; min.y is the floor level
+    pub min: IVec3,
+    pub max: IVec3,
+    pub chests: Vec<IVec3>,
+    // Where enemies should spawn; in the boss room the first one is the boss
+    pub enemy_spawns: Vec<IVec3>,
+    // Hand-authored structure furnishing the room, if any
+    pub structure: Option<RoomStructure>,
+}
+
+// Placement of one of the dungeon settings' structures in a room
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoomStructure {
+    // Index into the dungeon settings' structures
+    pub structure: usize,
+    // Lowest corner of the rotated structure
+    pub origin: IVec3,
+    pub rotation: Rotation,
+}
+
+impl DungeonRoom {
+    // Block at floor level in the middle of the room
+    pub fn center(&self) -> IVec3 {
+        IVec3::new((self.min.x + self.max.x).div_euclid(2), self.min.y, (self.min.z + self.max.z).div_euclid(2))
+    }
+
+    // Whether a column lies inside the room's interior
+    pub fn contains_column(&self, x: i32, z: i32) -> bool {
+        (self.min.x..=self.max.x).contains(&x) && (self.min.z..=self.max.z).contains(&z)
+    }
+
+    // Whether a column is part of the wall ring around the room
+    fn in_wall(&self, x: i32, z: i32) -> bool {
+        let around = (self.min.x - 1..=self.max.x + 1).contains(&x) && (self.min.z - 1..=self.max.z + 1).contains(&z);
+        around && !self.contains_column(x, z)
+    }
+
+    // Whether two rooms come closer than `margin` blocks apart, counting their walls
+    fn overlaps(&self, other: &DungeonRoom, margin: i32) -> bool {
+        let reach = margin + 2;
+        self.min.x - reach <= other.max.x
+            && other.min.x - reach <= self.max.x
+            && self.min.z - reach <= other.max.z
+            && other.min.z - reach <= self.max.z
+    }
+}
+
+// A corridor joining two rooms
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DungeonConnection {
+    // Indices of the joined rooms
+    pub rooms: (usize, usize),
+    // Floor level blocks the corridor runs through, from the first room's centre to the second's
+    pub path: Vec<IVec3>,
+    // Doorways where the corridor passes through a room wall
+    pub doors: Vec<IVec3>,
+}
+
+// A generated dungeon layout: its rooms and the corridors that connect them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dungeon {
+    pub rooms: Vec<DungeonRoom>,
+    pub connections: Vec<DungeonConnection>,
+    // Index of the entrance room
+    pub entrance: usize,
+    // Index of the boss room
+    pub boss_room: usize,
+    pub room_height: i32,
+    pub corridor_height: i32,
+}
+
+// Deterministic random numbers drawn from a seeded hash, in the order they are requested
+struct DungeonRng {
+    noise: Noise,
+    draws: i32,
+}
+
+impl DungeonRng {
+    // Uniform random value in 0..1
+    fn next(&mut self) -> f32 {
+        self.draws += 1;
+        self.noise.random(IVec3::new(self.draws, 0, 0))
+    }
+
+    // Uniform random integer in min..=max
+    fn range(&mut self, min: i32, max: i32) -> i32 {
+        if max <= min {
+            return min;
+        }
+        min + ((self.next() * (max - min + 1) as f32) as i32).min(max - min)
+    }
+}
+
+// Generates room-and-corridor dungeon layouts from a seed
+#[derive(Debug, Clone)]
+pub struct DungeonGenerator {
+    pub seed: u64,
+    pub settings: DungeonSettings,
+}
+
+impl DungeonGenerator {
+    pub fn new(seed: u64, settings: DungeonSettings) -> Self {
+        DungeonGenerator { seed, settings }
+    }
+
+    // Lay out a dungeon with its floor at `origin.y`, centred on `origin`.
+    // The layout depends only on the seed, settings and origin, so the same inputs always give the same dungeon.
+    pub fn generate(&self, origin: IVec3) -> Dungeon {
+        let settings = &self.settings;
+        let noise = Noise::new(self.seed).layer(DUNGEON_LAYER);
+        let mut rng = DungeonRng {
+            noise: noise.layer(noise.hash(origin)),
+            draws: 0,
+        };
+
+        // Scatter rooms, skipping any that would crowd a room already placed
+        let mut rooms: Vec<DungeonRoom> = Vec::new();
+        let min_size = settings.min_room_size.max(3);
+        let max_size = settings.max_room_size.max(min_size);
+        for _ in 0..settings.room_count * PLACEMENT_ATTEMPTS {
+            if rooms.len() == settings.room_count {
+                break;
+            }
+            let size = IVec3::new(rng.range(min_size, max_size), settings.room_height.max(2), rng.range(min_size, max_size));
+            let x = origin.x + rng.range(-settings.extent, settings.extent - size.x);
+            let z = origin.z + rng.range(-settings.extent, settings.extent - size.z);
+            let min = IVec3::new(x, origin.y, z);
+            let room = DungeonRoom {
+                kind: RoomKind::Chamber,
+                min,
+                max: min + size - IVec3::ONE,
+                chests: Vec::new(),
+                enemy_spawns: Vec::new(),
+                structure: None,
+            };
+            if rooms.iter().all(|other| !room.overlaps(other, ROOM_MARGIN)) {
+                rooms.push(room);
+            }
+        }
+        if rooms.is_empty() {
+            return Dungeon {
+                rooms,
+                connections: Vec::new(),
+                entrance: 0,
+                boss_room: 0,
+                room_height: settings.room_height,
+                corridor_height: settings.corridor_height,
+            };
+        }
+
+        // Join the rooms with a minimum spanning tree over their centres, then add a few loops
+        let distance = |a: usize, b: usize| (rooms[a].center() - rooms[b].center()).length_squared();
+        let mut edges: BTreeSet<(usize, usize)> = BTreeSet::new();
+        let mut joined = vec![false; rooms.len()];
+        joined[0] = true;
+        for _ in 1..rooms.len() {
+            let (_, a, b) = (0..rooms.len())
+                .filter(|&a| joined[a])
+                .flat_map(|a| (0..rooms.len()).filter(|&b| !joined[b]).map(move |b| (a, b)))
+                .map(|(a, b)| (distance(a, b), a, b))
+                .min()
+                .unwrap();
+            joined[b] = true;
+            edges.insert((a.min(b), a.max(b)));
+        }
+        for a in 0..rooms.len() {
+            let nearest = (0..rooms.len())
+                .filter(|&b| b != a && !edges.contains(&(a.min(b), a.max(b))))
+                .min_by_key(|&b| (distance(a, b), b));
+            if let Some(b) = nearest {
+                if rng.next() < settings.loop_chance {
+                    edges.insert((a.min(b), a.max(b)));
+                }
+            }
+        }
+
+        // The entrance is the room nearest the origin and the boss waits in the room the most corridors away from it
+        let entrance = (0..rooms.len())
+            .min_by_key(|&room| ((rooms[room].center() - origin).length_squared(), room))
+            .unwrap();
+        let mut hops = vec![usize::MAX; rooms.len()];
+        hops[entrance] = 0;
+        let mut queue = VecDeque::from([entrance]);
+        while let Some(room) = queue.pop_front() {
+            for &(a, b) in &edges {
+                let next = if a == room { b } else if b == room { a } else { continue };
+                if hops[next] == usize::MAX {
+                    hops[next] = hops[room] + 1;
+                    queue.push_back(next);
+                }
+            }
+        }
+        let boss_room = (0..rooms.len())
+            .max_by_key(|&room| (hops[room], distance(room, entrance), std::cmp::Reverse(room)))
+            .unwrap();
+        rooms[entrance].kind = RoomKind::Entrance;
+        if boss_room != entrance {
+            rooms[boss_room].kind = RoomKind::Boss;
+        }
+
+        let connections: Vec<DungeonConnection> = edges.into_iter().map(|(a, b)| Self::connect(&rooms, a, b)).collect();
+
+        // Furnish the rooms; chests go in corners no corridor runs through, so they never block the way
+        for room in rooms.iter_mut() {
+            if room.kind == RoomKind::Entrance {
+                continue;
+            }
+            if room.kind == RoomKind::Boss {
+                room.enemy_spawns.push(room.center());
+            }
+
+            // Set pieces bring their own chests and enemies through their markers
+            if !settings.structures.is_empty() && rng.next() < settings.structure_chance {
+                let index = rng.range(0, settings.structures.len() as i32 - 1) as usize;
+                let rotation = Rotation::from_quarter_turns(rng.range(0, 3));
+                let structure = &settings.structures[index];
+                let size = rotation.rotate_size(structure.dimensions());
+                let interior = room.max - room.min + IVec3::ONE;
+                // The structure's ground layer replaces the floor and it must stay under the ceiling
+                if size.x <= interior.x && size.z <= interior.z && size.y - structure.ground_level <= interior.y + 1 {
+                    let offset = (interior - size) / 2;
+                    let origin = IVec3::new(room.min.x + offset.x, room.min.y - 1 - structure.ground_level, room.min.z + offset.z);
+                    for (position, kind) in structure.markers(origin, rotation) {
+                        match kind {
+                            MarkerKind::Chest => room.chests.push(position),
+                            MarkerKind::Spawner { .. } => room.enemy_spawns.push(position),
+                        }
+                    }
+                    room.structure = Some(RoomStructure { structure: index, origin, rotation });
+                    continue;
+                }
+            }
+
+            let corners: Vec<IVec3> = [
+                IVec3::new(room.min.x, room.min.y, room.min.z),
+                IVec3::new(room.max.x, room.min.y, room.min.z),
+                IVec3::new(room.min.x, room.min.y, room.max.z),
+                IVec3::new(room.max.x, room.min.y, room.max.z),
+            ]
+            .into_iter()
+            .filter(|corner| connections.iter().all(|connection| !connection.path.contains(corner)))
+            .collect();
+            if (room.kind == RoomKind::Boss || rng.next() < settings.chest_chance) && !corners.is_empty() {
+                room.chests.push(corners[rng.range(0, corners.len() as i32 - 1) as usize]);
+            }
+            let enemies = rng.range(1, settings.max_enemies_per_room.max(1));
+            for _ in 0..enemies {
+                let spawn = IVec3::new(rng.range(room.min.x, room.max.x), room.min.y, rng.range(room.min.z, room.max.z));
+                if !room.chests.contains(&spawn) && !room.enemy_spawns.contains(&spawn) {
+                    room.enemy_spawns.push(spawn);
+                }
+            }
+        }
+
+        Dungeon {
+            rooms,
+            connections,
+            entrance,
+            boss_room,
+            room_height: settings.room_height,
+            corridor_height: settings.corridor_height,
+        }
+    }
+
+    // Run an L-shaped corridor from one room's centre to another's, turning at whichever corner runs it through fewer walls
+    fn connect(rooms: &[DungeonRoom], a: usize, b: usize) -> DungeonConnection {
+        let (start, end) = (rooms[a].center(), rooms[b].center());
+        let walk = |first_axis: usize| {
+            let mut path = vec![start];
+            let mut current = start;
+            for axis in [first_axis, 2 - first_axis] {
+                while current[axis] != end[axis] {
+                    current[axis] += (end[axis] - current[axis]).signum();
+                    path.push(current);
+                }
+            }
+            path
+        };
+        let walls = |path: &Vec<IVec3>| path.iter().filter(|block| rooms.iter().any(|room| room.in_wall(block.x, block.z))).count();
+        let (along_x, along_z) = (walk(0), walk(2));
+        let path = if walls(&along_z) < walls(&along_x) { along_z } else { along_x };
+
+        // A doorway is where the corridor crosses a room wall, stepping between the room and the outside
+        let mut doors = Vec::new();
+        for (index, block) in path.iter().enumerate() {
+            let steps_inside = |room: &DungeonRoom| {
+                let before = index.checked_sub(1).map(|before| path[before]);
+                let after = path.get(index + 1).copied();
+                [before, after].into_iter().flatten().any(|next| room.contains_column(next.x, next.z))
+            };
+            let crosses_wall = rooms.iter().any(|room| room.in_wall(block.x, block.z) && steps_inside(room));
+            let inside_room = rooms.iter().any(|room| room.contains_column(block.x, block.z));
+            if crosses_wall && !inside_room {
+                doors.push(*block);
+            }
+        }
+        DungeonConnection { rooms: (a, b), path, doors }
+    }
+}
+
+impl Dungeon {
+    // Indices of the rooms joined to a room by a corridor
+    pub fn neighbors(&self, room: usize) -> impl Iterator<Item = usize> + '_ {
+        self.connections.iter().filter_map(move |connection| match connection.rooms {
+            (a, b) if a == room => Some(b),
+            (a, b) if b == room => Some(a),
+            _ => None,
+        })
+    }
+
+    // Every block the dungeon replaces with its world block coordinate, in coordinate order
+    pub fn blocks(&self, registry: &BlockRegistry, settings: &DungeonSettings) -> Result<Vec<(IVec3, BlockId)>, DungeonError> {
+        let id = |name: &str| registry.id(name).ok_or_else(|| DungeonError::UnknownBlock(name.to_string()));
+        let (wall, floor, door, chest) =
+            (id(&settings.wall_block)?, id(&settings.floor_block)?, id(&settings.door_block)?, id(&settings.chest_block)?);
+
+        // Open height of every column the dungeon hollows out, keyed by floor level block
+        let mut open: BTreeMap<[i32; 3], i32> = BTreeMap::new();
+        for room in &self.rooms {
+            for x in room.min.x..=room.max.x {
+                for z in room.min.z..=room.max.z {
+                    open.insert([x, room.min.y, z], room.max.y - room.min.y + 1);
+                }
+            }
+        }
+        let doors: HashSet<IVec3> = self.connections.iter().flat_map(|connection| connection.doors.iter().copied()).collect();
+        for connection in &self.connections {
+            for &block in &connection.path {
+                // Doorways are two blocks tall under a lintel
+                let height = if doors.contains(&block) { 2 } else { self.corridor_height };
+                let entry = open.entry(block.to_array()).or_insert(height);
+                *entry = (*entry).max(height);
+            }
+        }
+
+        // Walls first, so the open columns carved after them always win
+        let mut blocks = BTreeMap::new();
+        let mut place = |world: IVec3, block: BlockId| {
+            blocks.insert(world.to_array(), block);
+        };
+        for (&base, &height) in &open {
+            for dx in -1..=1 {
+                for dz in -1..=1 {
+                    let side = IVec3::from_array(base) + IVec3::new(dx, 0, dz);
+                    if open.contains_key(&side.to_array()) {
+                        continue;
+                    }
+                    for y in -1..=height {
+                        place(side + IVec3::Y * y, wall);
+                    }
+                }
+            }
+        }
+        for (&base, &height) in &open {
+            let base = IVec3::from_array(base);
+            place(base - IVec3::Y, floor);
+            for y in 0..height {
+                place(base + IVec3::Y * y, AIR);
+            }
+            place(base + IVec3::Y * height, wall);
+        }
+        for room in &self.rooms {
+            let Some(placement) = room.structure else {
+                continue;
+            };
+            let Some(structure) = settings.structures.get(placement.structure) else {
+                continue;
+            };
+            for (world, block) in structure.blocks(registry, placement.origin, placement.rotation).map_err(DungeonError::Structure)? {
+                place(world, block);
+            }
+        }
+        for &block in &doors {
+            place(block, door);
+            place(block + IVec3::Y, door);
+        }
+        for room in &self.rooms {
+            for &block in &room.chests {
+                place(block, chest);
+            }
+        }
+        Ok(blocks.into_iter().map(|(world, block)| (IVec3::from_array(world), block)).collect())
+    }
+}
+
+impl VoxelTerrain {
+    // Carve a dungeon into the terrain, generating or restoring the chunks it reaches first
+    pub fn carve_dungeon(&mut self, dungeon: &Dungeon, registry: &BlockRegistry, settings: &DungeonSettings) -> Result<(), DungeonError> {
+        let blocks = dungeon.blocks(registry, settings)?;
+        self.stamp_blocks(blocks, registry);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::voxel_terrain::test_registry;
+
+    #[test]
+    fn same_seed_and_origin_give_same_dungeon() {
+        let origin = IVec3::new(100, -30, -40);
+        let first = DungeonGenerator::new(9, DungeonSettings::default()).generate(origin);
+        let second = DungeonGenerator::new(9, DungeonSettings::default()).generate(origin);
+        assert_eq!(first, second);
+        assert!(first.rooms.len() > 1);
+
+        assert_ne!(first, DungeonGenerator::new(10, DungeonSettings::default()).generate(origin));
+        assert_ne!(first, DungeonGenerator::new(9, DungeonSettings::default()).generate(origin + IVec3::X * 500));
+    }
+
+    #[test]
+    fn fixed_seed_gives_known_layout() {
+        // Dungeons come from the world seed, so a change to this layout moves them in existing worlds
+        let dungeon = DungeonGenerator::new(9, DungeonSettings::default()).generate(IVec3::new(100, -30, -40));
+        let rooms: Vec<(RoomKind, IVec3, IVec3)> = dungeon.rooms.iter().map(|room| (room.kind, room.min, room.max)).collect();
+        assert_eq!(
+            rooms,
+            [
+                (RoomKind::Entrance, IVec3::new(104, -30, -33), IVec3::new(108, -27, -28)),
+                (RoomKind::Chamber, IVec3::new(135, -30, -50), IVec3::new(139, -27, -46)),
+                (RoomKind::Boss, IVec3::new(62, -30, -63), IVec3::new(70, -27, -56)),
+                (RoomKind::Chamber, IVec3::new(69, -30, -7), IVec3::new(75, -27, -2)),
+                (RoomKind::Chamber, IVec3::new(84, -30, -77), IVec3::new(94, -27, -67)),
+                (RoomKind::Chamber, IVec3::new(77, -30, -32), IVec3::new(81, -27, -22)),
+                (RoomKind::Chamber, IVec3::new(104, -30, -21), IVec3::new(108, -27, -17)),
+                (RoomKind::Chamber, IVec3::new(108, -30, -55), IVec3::new(118, -27, -45)),
+            ]
+        );
+        let connections: Vec<(usize, usize)> = dungeon.connections.iter().map(|connection| connection.rooms).collect();
+        assert_eq!(
+            connections,
+            [(0, 1), (0, 4), (0, 5), (0, 6), (0, 7), (1, 7), (2, 4), (3, 5), (3, 6), (4, 7), (5, 6)]
+        );
+        assert_eq!(dungeon.entrance, 0);
+        assert_eq!(dungeon.boss_room, 2);
+    }
+
+    #[test]
+    fn every_room_is_reachable_from_entrance() {
+        let registry = test_registry();
+        let settings = DungeonSettings::default();
+        let door = registry.id(&settings.door_block).unwrap();
+        for seed in 0..16 {
+            let origin = IVec3::new(0, -20, 0);
+            let dungeon = DungeonGenerator::new(seed, settings.clone()).generate(origin);
+
+            // Through the corridors joining the rooms
+            let mut reached = HashSet::from([dungeon.entrance]);
+            let mut queue = VecDeque::from([dungeon.entrance]);
+            while let Some(room) = queue.pop_front() {
+                for next in dungeon.neighbors(room) {
+                    if reached.insert(next) {
+                        queue.push_back(next);
+                    }
+                }
+            }
+            assert_eq!(reached.len(), dungeon.rooms.len(), "seed {} has rooms no corridor leads to", seed);
+
+            // And on foot through the carved blocks, walking the floor level past doors
+            let blocks: HashMap<IVec3, BlockId> = dungeon.blocks(&registry, &settings).unwrap().into_iter().collect();
+            let walkable = |world: &IVec3| matches!(blocks.get(world), Some(&block) if block == AIR || block == door);
+            let start = dungeon.rooms[dungeon.entrance].center();
+            let mut visited = HashSet::from([start]);
+            let mut queue = VecDeque::from([start]);
+            while let Some(world) = queue.pop_front() {
+                for step in [IVec3::X, IVec3::NEG_X, IVec3::Z, IVec3::NEG_Z] {
+                    let next = world + step;
+                    if walkable(&next) && visited.insert(next) {
+                        queue.push_back(next);
+                    }
+                }
+            }
+            for (index, room) in dungeon.rooms.iter().enumerate() {
+                assert!(visited.contains(&room.center()), "seed {} room {} cannot be walked to", seed, index);
+            }
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use bevy::prelude::*;
+
+use super::{BlockId, BlockRegistry, VoxelTerrain, AIR};
+
+// Request to change the block at a world block coordinate; edits are applied in the order they were sent,
+// so breaking a block and placing another in the same frame leaves the new block behind
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockEdit {
+    // Replace the block
+    Set { position: IVec3, block: BlockId },
+    // Break the block, leaving air behind
+    Break { position: IVec3 },
+}
+
+// Sent after a block edit has changed the terrain; blocks changed by simulations such as fluids are not reported
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockChanged {
+    pub position: IVec3,
+    pub previous: BlockId,
+    pub block: BlockId,
+}
+
+// System to apply block edit events to the terrain's chunk data
+pub fn apply_block_edits(
+    mut terrain: ResMut<VoxelTerrain>,
+    registry: Option<Res<BlockRegistry>>,
+    mut edits: EventReader<BlockEdit>,
+    mut changed_events: EventWriter<BlockChanged>,
+) {
+    for edit in edits.read() {
+        let (position, block) = match *edit {
+            BlockEdit::Set { position, block } => (position, block),
+            BlockEdit::Break { position } => {
+                let current = terrain.get_block(position);
+                // Negative hardness marks blocks that cannot be broken, such as fluids
+                let unbreakable = registry.as_ref().is_some_and(|registry| registry.hardness(current) < 0.0);
+                if current == AIR || unbreakable {
+                    continue;
+                }
+                (position, AIR)
+            }
+        };
+        let previous = terrain.set_block(position, block);
+        if previous != block {
+            changed_events.send(BlockChanged { position, previous, block });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::voxel_terrain::{test_registry, VoxelTerrainPlugin};
+
+    // Headless app editing a terrain with a stone block at the origin, with nothing left dirty or reported yet
+    fn edit_app() -> App {
+        let registry = test_registry();
+        let mut terrain = VoxelTerrain::new(Vec3::ZERO, 1.0);
+        terrain.set_block(IVec3::ZERO, registry.id("stone").unwrap());
+        terrain.set_block(IVec3::new(2, 0, 0), registry.id("water").unwrap());
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, VoxelTerrainPlugin))
+            .insert_resource(registry)
+            .insert_resource(terrain);
+        app.update();
+        app.world.resource_mut::<VoxelTerrain>().dirty_chunks.clear();
+        app
+    }
+
+    fn edit(app: &mut App, edits: impl IntoIterator<Item = BlockEdit>) -> Vec<BlockChanged> {
+        for edit in edits {
+            app.world.send_event(edit);
+        }
+        app.update();
+        app.world.resource_mut::<Events<BlockChanged>>().drain().collect()
+    }
+
+    #[test]
+    fn edits_apply_in_the_order_they_were_sent() {
+        let mut app = edit_app();
+        let registry = test_registry();
+        let (stone, dirt) = (registry.id("stone").unwrap(), registry.id("dirt").unwrap());
+        let other = IVec3::new(5, 5, 5);
+        let changes = edit(
+            &mut app,
+            [
+                BlockEdit::Break { position: IVec3::ZERO },
+                BlockEdit::Set { position: IVec3::ZERO, block: dirt },
+                BlockEdit::Set { position: other, block: dirt },
+                BlockEdit::Break { position: other },
+            ],
+        );
+
+        let terrain = app.world.resource::<VoxelTerrain>();
+        assert_eq!(terrain.chunk(IVec3::ZERO).unwrap().get(UVec3::ZERO), dirt);
+        assert_eq!(terrain.get_block(other), AIR);
+        assert_eq!(
+            changes,
+            vec![
+                BlockChanged { position: IVec3::ZERO, previous: stone, block: AIR },
+                BlockChanged { position: IVec3::ZERO, previous: AIR, block: dirt },
+                BlockChanged { position: other, previous: AIR, block: dirt },
+                BlockChanged { position: other, previous: dirt, block: AIR },
+            ]
+        );
+    }
+
+    #[test]
+    fn border_edits_dirty_neighbouring_chunks() {
+        let mut app = edit_app();
+        let dirt = test_registry().id("dirt").unwrap();
+
+        // Relighting may also dirty the chunks below an edit, so only the ones beside it are checked exactly
+        let sideways = |dirty: &HashSet<IVec3>| -> HashSet<IVec3> { dirty.iter().copied().filter(|chunk| chunk.y == 0).collect() };
+
+        edit(&mut app, [BlockEdit::Set { position: IVec3::new(8, 8, 8), block: dirt }]);
+        let dirty = std::mem::take(&mut app.world.resource_mut::<VoxelTerrain>().dirty_chunks);
+        assert_eq!(sideways(&dirty), HashSet::from([IVec3::ZERO]));
+
+        // A block in the corner of its chunk touches the three chunks beyond its faces
+        edit(&mut app, [BlockEdit::Set { position: IVec3::new(-1, 15, 0), block: dirt }]);
+        let terrain = app.world.resource::<VoxelTerrain>();
+        assert_eq!(terrain.chunk(IVec3::new(-1, 0, 0)).unwrap().get(UVec3::new(15, 15, 0)), dirt);
+        let neighbours = [IVec3::new(-1, 0, 0), IVec3::new(0, 0, 0), IVec3::new(-1, 1, 0), IVec3::new(-1, 0, -1)];
+        for chunk in neighbours {
+            assert!(terrain.dirty_chunks.contains(&chunk), "chunk {} was not dirtied", chunk);
+        }
+        assert!(terrain.edited_chunks.contains(&IVec3::new(-1, 0, 0)));
+    }
+
+    #[test]
+    fn edits_that_change_nothing_are_not_reported() {
+        let mut app = edit_app();
+        let stone = test_registry().id("stone").unwrap();
+        let changes = edit(
+            &mut app,
+            [
+                BlockEdit::Set { position: IVec3::ZERO, block: stone },
+                BlockEdit::Break { position: IVec3::new(1, 0, 0) },
+                // Fluids cannot be broken
+                BlockEdit::Break { position: IVec3::new(2, 0, 0) },
+            ],
+        );
+        assert!(changes.is_empty());
+        assert!(app.world.resource::<VoxelTerrain>().dirty_chunks.is_empty());
+    }
+}
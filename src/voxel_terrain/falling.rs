@@ -0,0 +1,357 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+
+use bevy::prelude::*;
+
+use super::{world_to_chunk, BlockChanged, BlockId, BlockRegistry, Face, FluidSimulation, VoxelTerrain, AIR};
+
+// Sent when a falling block comes to rest, so entities caught underneath can be hurt
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockLanded {
+    // World block coordinate the block landed in
+    pub position: IVec3,
+    pub block: BlockId,
+    // Number of blocks it fell
+    pub distance: u32,
+}
+
+// State of blocks falling under gravity, such as sand and collapsing overhangs.
+// Like fluids, falling blocks only move on fixed ticks and in coordinate order, so they are deterministic.
+#[derive(Resource, Debug, Clone)]
+pub struct FallingBlocks {
+    // Fixed ticks between each one block drop
+    pub ticks_per_fall: u64,
+    // Make overhangs that reach too far from any support collapse after blocks are broken
+    pub structural_integrity: bool,
+    // Furthest a block may reach sideways or hang down from a supported block before collapsing
+    pub max_overhang: i32,
+    // Number of fixed ticks simulated so far
+    pub tick: u64,
+    // Blocks in motion and how far they have fallen
+    falling: HashMap<IVec3, u32>,
+    // Blocks due for a check, keyed by the tick they are due on, ordered bottom up as [y, x, z]
+    scheduled: BTreeMap<u64, BTreeSet<[i32; 3]>>,
+    // Checks that came due outside every loader's simulation radius
+    dormant: BTreeSet<[i32; 3]>,
+}
+
+impl Default for FallingBlocks {
+    fn default() -> Self {
+        FallingBlocks {
+            ticks_per_fall: 4,
+            structural_integrity: false,
+            max_overhang: 5,
+            tick: 0,
+            falling: HashMap::new(),
+            scheduled: BTreeMap::new(),
+            dormant: BTreeSet::new(),
+        }
+    }
+}
+
+impl FallingBlocks {
+    // Queue a block to be checked `delay` ticks from now
+    pub fn schedule(&mut self, world: IVec3, delay: u64) {
+        self.scheduled.entry(self.tick + delay.max(1)).or_default().insert([world.y, world.x, world.z]);
+    }
+
+    // Whether no block is falling or due for a check
+    pub fn is_settled(&self) -> bool {
+        self.scheduled.is_empty() && self.falling.is_empty()
+    }
+
+    // Whether a block is currently falling
+    pub fn is_falling(&self, world: IVec3) -> bool {
+        self.falling.contains_key(&world)
+    }
+
+    // Make a block fall regardless of its type, as when it loses structural support
+    pub fn release(&mut self, world: IVec3) {
+        self.falling.entry(world).or_insert(0);
+        self.schedule(world, 1);
+    }
+
+    // Release every block near a broken block that is too far from any support.
+    // Support is spread from the edge of a region around the break, which is assumed to be anchored:
+    // it carries straight up through stacked blocks and fades by one per block sideways or downward.
+    // The region reaches twice as far as support does, so the assumed anchors cannot hold up blocks next to the break.
+    pub fn collapse_unsupported(&mut self, terrain: &VoxelTerrain, registry: &BlockRegistry, broken: IVec3) {
+        let full = self.max_overhang + 1;
+        let radius = full * 2;
+        let solid = |world: IVec3| {
+            let block = terrain.get_block(world);
+            block != AIR && registry.is_solid(block)
+        };
+        let in_region = |world: IVec3| (world - broken).abs().max_element() <= radius;
+
+        let mut support: HashMap<IVec3, i32> = HashMap::new();
+        let mut queue = VecDeque::new();
+        for x in -radius..=radius {
+            for y in -radius..=radius {
+                for z in -radius..=radius {
+                    let offset = IVec3::new(x, y, z);
+                    let world = broken + offset;
+                    if offset.abs().max_element() == radius && solid(world) {
+                        support.insert(world, full);
+                        queue.push_back(world);
+                    }
+                }
+            }
+        }
+        while let Some(world) = queue.pop_front() {
+            let level = support[&world];
+            for face in Face::ALL {
+                let neighbor = world + face.normal();
+                let spread = if face == Face::PosY { level } else { level - 1 };
+                if spread <= 0 || !in_region(neighbor) || support.get(&neighbor).is_some_and(|&current| current >= spread) {
+                    continue;
+                }
+                if solid(neighbor) {
+                    support.insert(neighbor, spread);
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+
+        // Release the unsupported blocks connected to the break
+        let mut visited = HashSet::new();
+        let mut queue: VecDeque<IVec3> = Face::ALL.iter().map(|face| broken + face.normal()).collect();
+        while let Some(world) = queue.pop_front() {
+            if !in_region(world) || support.contains_key(&world) || !solid(world) || !visited.insert(world) {
+                continue;
+            }
+            self.release(world);
+            queue.extend(Face::ALL.map(|face| world + face.normal()));
+        }
+    }
+
+    // Advance falling blocks by one fixed tick, returning the blocks that landed
+    pub fn step(
+        &mut self,
+        terrain: &mut VoxelTerrain,
+        registry: &BlockRegistry,
+        mut fluids: Option<&mut FluidSimulation>,
+    ) -> Vec<BlockLanded> {
+        self.tick += 1;
+        let mut due = self.scheduled.remove(&self.tick).unwrap_or_default();
+        let resumed: Vec<[i32; 3]> = self
+            .dormant
+            .iter()
+            .filter(|[y, x, z]| terrain.is_simulated(world_to_chunk(IVec3::new(*x, *y, *z)).0))
+            .copied()
+            .collect();
+        for key in resumed {
+            self.dormant.remove(&key);
+            due.insert(key);
+        }
+
+        // Bottom up, so a stack whose lower blocks move first falls together
+        let mut landed = Vec::new();
+        while let Some([y, x, z]) = due.pop_first() {
+            let world = IVec3::new(x, y, z);
+            if !terrain.is_simulated(world_to_chunk(world).0) {
+                self.dormant.insert([y, x, z]);
+                continue;
+            }
+            let block = terrain.get_block(world);
+            let distance = self.falling.remove(&world);
+            if block == AIR || !(registry.falls(block) || distance.is_some()) {
+                continue;
+            }
+
+            let below = world - IVec3::Y;
+            let blocked = registry.is_solid(terrain.get_block(below)) || !terrain.is_simulated(world_to_chunk(below).0);
+            if blocked {
+                if let Some(distance) = distance.filter(|&distance| distance > 0) {
+                    landed.push(BlockLanded { position: world, block, distance });
+                }
+                continue;
+            }
+
+            // Whatever non-solid block was below, such as water or tall grass, is crushed
+            terrain.set_block(below, block);
+            terrain.set_block(world, AIR);
+            terrain.update_light(below, registry);
+            terrain.update_light(world, registry);
+            self.falling.insert(below, distance.unwrap_or(0) + 1);
+            self.schedule(below, self.ticks_per_fall);
+            // The block above may have been resting on this one; it follows in the same tick so stacks stay in one piece
+            due.insert([y + 1, x, z]);
+            if let Some(fluids) = fluids.as_deref_mut() {
+                fluids.wake(world);
+                fluids.wake(below);
+            }
+        }
+        landed
+    }
+}
+
+// System to start blocks falling after edits remove what held them up
+pub fn wake_falling_blocks(
+    mut falling: ResMut<FallingBlocks>,
+    terrain: Res<VoxelTerrain>,
+    registry: Res<BlockRegistry>,
+    mut changes: EventReader<BlockChanged>,
+) {
+    for change in changes.read() {
+        // A placed block may itself fall, and a removed one may leave the block above hanging
+        falling.schedule(change.position, 1);
+        falling.schedule(change.position + IVec3::Y, 1);
+        if falling.structural_integrity && !registry.is_solid(change.block) && registry.is_solid(change.previous) {
+            falling.collapse_unsupported(&terrain, &registry, change.position);
+        }
+    }
+}
+
+// System to move falling blocks on the fixed timestep
+pub fn tick_falling_blocks(
+    mut falling: ResMut<FallingBlocks>,
+    mut terrain: ResMut<VoxelTerrain>,
+    registry: Res<BlockRegistry>,
+    fluids: Option<ResMut<FluidSimulation>>,
+    mut landed_events: EventWriter<BlockLanded>,
+) {
+    let landed = falling.step(&mut terrain, &registry, fluids.map(|fluids| fluids.into_inner()));
+    landed_events.send_batch(landed);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+    use crate::voxel_terrain::{test_registry, BlockEdit, VoxelTerrainPlugin};
+
+    // Headless app on solid stone ground up to y = 0, advancing one fixed tick per update
+    fn falling_app(registry: &BlockRegistry, terrain: VoxelTerrain, structural_integrity: bool) -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, VoxelTerrainPlugin))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(1.0 / 64.0)))
+            .insert_resource(registry.clone())
+            .insert_resource(terrain)
+            .insert_resource(FallingBlocks {
+                structural_integrity,
+                ..default()
+            });
+        app.update();
+        app
+    }
+
+    fn ground(registry: &BlockRegistry) -> VoxelTerrain {
+        let stone = registry.id("stone").unwrap();
+        let mut terrain = VoxelTerrain::new(Vec3::ZERO, 1.0);
+        for x in -16..32 {
+            for y in -12..=0 {
+                for z in -16..32 {
+                    terrain.set_block(IVec3::new(x, y, z), stone);
+                }
+            }
+        }
+        terrain
+    }
+
+    // Run fixed ticks until nothing is falling, collecting every block that landed
+    fn settle(app: &mut App, mut on_tick: impl FnMut(&VoxelTerrain)) -> Vec<BlockLanded> {
+        let mut landed = Vec::new();
+        for _ in 0..500 {
+            app.update();
+            landed.extend(app.world.resource_mut::<Events<BlockLanded>>().drain());
+            on_tick(app.world.resource::<VoxelTerrain>());
+            if app.world.resource::<FallingBlocks>().is_settled() {
+                return landed;
+            }
+        }
+        panic!("blocks never settled");
+    }
+
+    #[test]
+    fn unsupported_sand_falls_and_lands() {
+        let registry = test_registry();
+        let sand = registry.id("sand").unwrap();
+        let mut app = falling_app(&registry, ground(&registry), false);
+        app.world.send_event(BlockEdit::Set { position: IVec3::new(4, 10, 4), block: sand });
+
+        let landed = settle(&mut app, |_| {});
+        assert_eq!(landed, vec![BlockLanded { position: IVec3::new(4, 1, 4), block: sand, distance: 9 }]);
+        let terrain = app.world.resource::<VoxelTerrain>();
+        assert_eq!(terrain.get_block(IVec3::new(4, 1, 4)), sand);
+        assert_eq!(terrain.get_block(IVec3::new(4, 10, 4)), AIR);
+
+        // Sand placed on the ground is already resting, so nothing lands
+        app.world.send_event(BlockEdit::Set { position: IVec3::new(6, 1, 6), block: sand });
+        assert!(settle(&mut app, |_| {}).is_empty());
+    }
+
+    #[test]
+    fn sand_stack_falls_together() {
+        let registry = test_registry();
+        let (stone, sand) = (registry.id("stone").unwrap(), registry.id("sand").unwrap());
+        // Three sand blocks resting on a stone pillar four blocks high
+        let mut terrain = ground(&registry);
+        for y in 1..=4 {
+            terrain.set_block(IVec3::new(4, y, 4), stone);
+        }
+        for y in 5..=7 {
+            terrain.set_block(IVec3::new(4, y, 4), sand);
+        }
+        let mut app = falling_app(&registry, terrain, false);
+        for y in 1..=4 {
+            app.world.send_event(BlockEdit::Break { position: IVec3::new(4, y, 4) });
+        }
+
+        let landed = settle(&mut app, |terrain| {
+            let heights: Vec<i32> = (1..=7).filter(|&y| terrain.get_block(IVec3::new(4, y, 4)) == sand).collect();
+            assert_eq!(heights.len(), 3, "sand was lost or duplicated");
+            assert_eq!(heights[2] - heights[0], 2, "stack came apart: {:?}", heights);
+        });
+        let mut distances: Vec<(i32, u32)> = landed.iter().map(|landed| (landed.position.y, landed.distance)).collect();
+        distances.sort();
+        assert_eq!(distances, vec![(1, 4), (2, 4), (3, 4)]);
+    }
+
+    // Ground with a pillar four blocks high at x = 8 and an arm reaching four blocks sideways from its top
+    fn overhang(registry: &BlockRegistry) -> VoxelTerrain {
+        let stone = registry.id("stone").unwrap();
+        let mut terrain = ground(registry);
+        for y in 1..=4 {
+            terrain.set_block(IVec3::new(8, y, 8), stone);
+        }
+        for x in 9..=12 {
+            terrain.set_block(IVec3::new(x, 4, 8), stone);
+        }
+        terrain
+    }
+
+    #[test]
+    fn overhangs_collapse_with_structural_integrity() {
+        let registry = test_registry();
+        let stone = registry.id("stone").unwrap();
+        let mut app = falling_app(&registry, overhang(&registry), true);
+        app.world.send_event(BlockEdit::Break { position: IVec3::new(8, 2, 8) });
+        settle(&mut app, |_| {});
+
+        // The pillar's top and the whole arm came down onto the ground
+        let terrain = app.world.resource::<VoxelTerrain>();
+        for y in 1..=3 {
+            assert_eq!(terrain.get_block(IVec3::new(8, y, 8)), stone, "pillar has a gap at y = {}", y);
+        }
+        assert_eq!(terrain.get_block(IVec3::new(8, 4, 8)), AIR);
+        for x in 9..=12 {
+            assert_eq!(terrain.get_block(IVec3::new(x, 4, 8)), AIR, "arm still hangs at x = {}", x);
+            assert_eq!(terrain.get_block(IVec3::new(x, 1, 8)), stone, "arm did not land at x = {}", x);
+        }
+    }
+
+    #[test]
+    fn overhangs_stay_without_structural_integrity() {
+        let registry = test_registry();
+        let mut app = falling_app(&registry, overhang(&registry), false);
+        app.world.send_event(BlockEdit::Break { position: IVec3::new(8, 2, 8) });
+        settle(&mut app, |_| {});
+
+        let mut expected = overhang(&registry);
+        expected.set_block(IVec3::new(8, 2, 8), AIR);
+        assert_eq!(app.world.resource::<VoxelTerrain>().chunks, expected.chunks);
+    }
+}
@@ -0,0 +1,365 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use bevy::prelude::*;
+
+use super::{chunk_to_world, world_to_chunk, BlockChanged, BlockId, BlockRegistry, Chunk, Face, VoxelTerrain, AIR, CHUNK_SIZE};
+
+// Block a fluid turns into when it touches another block, such as lava cooling into stone next to water
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FluidReaction {
+    pub fluid: String,
+    pub touching: String,
+    pub product: String,
+}
+
+impl FluidReaction {
+    pub fn new(fluid: &str, touching: &str, product: &str) -> Self {
+        FluidReaction {
+            fluid: fluid.to_string(),
+            touching: touching.to_string(),
+            product: product.to_string(),
+        }
+    }
+}
+
+// Pending updates of water, lava and any other fluid blocks; the levels of flowing blocks are kept in the chunks.
+// Fluids only change on fixed ticks and updates are processed in coordinate order, so a simulation
+// fed the same edits always produces the same world.
+#[derive(Resource, Debug, Clone)]
+pub struct FluidSimulation {
+    pub reactions: Vec<FluidReaction>,
+    // Number of fixed ticks simulated so far
+    pub tick: u64,
+    // Blocks due for an update, keyed by the tick they are due on
+    scheduled: BTreeMap<u64, BTreeSet<[i32; 3]>>,
+    // Updates that came due outside every loader's simulation radius, resumed once the chunk is simulated again
+    dormant: BTreeSet<[i32; 3]>,
+    // Chunks that were loaded on the last tick
+    loaded: HashSet<IVec3>,
+}
+
+impl Default for FluidSimulation {
+    fn default() -> Self {
+        FluidSimulation {
+            reactions: vec![FluidReaction::new("lava", "water", "stone")],
+            tick: 0,
+            scheduled: BTreeMap::new(),
+            dormant: BTreeSet::new(),
+            loaded: HashSet::new(),
+        }
+    }
+}
+
+// Horizontal directions fluids spread in, in the order neighbours are considered
+const HORIZONTAL: [Face; 4] = [Face::PosX, Face::NegX, Face::PosZ, Face::NegZ];
+
+impl FluidSimulation {
+    // Fluid block and level at a world block coordinate, if the block is a fluid
+    pub fn level(&self, terrain: &VoxelTerrain, registry: &BlockRegistry, world: IVec3) -> Option<(BlockId, u8)> {
+        let block = terrain.get_block(world);
+        let fluid = registry.fluid(block)?;
+        Some((block, terrain.fluid_level(world).unwrap_or(fluid.source_level())))
+    }
+
+    // Queue a block for an update `delay` ticks from now
+    pub fn schedule(&mut self, world: IVec3, delay: u64) {
+        self.scheduled.entry(self.tick + delay.max(1)).or_default().insert(world.to_array());
+    }
+
+    // Queue a block and its neighbours for an update on the next tick
+    pub fn wake(&mut self, world: IVec3) {
+        self.schedule(world, 1);
+        for face in Face::ALL {
+            self.schedule(world + face.normal(), 1);
+        }
+    }
+
+    // Whether no fluid update is pending in a simulated chunk
+    pub fn is_settled(&self) -> bool {
+        self.scheduled.is_empty()
+    }
+
+    // Block and flowing level a block should have next, or None if it stays as it is
+    fn next_state(
+        &self,
+        terrain: &VoxelTerrain,
+        registry: &BlockRegistry,
+        reactions: &[(BlockId, BlockId, BlockId)],
+        world: IVec3,
+    ) -> Option<(BlockId, Option<u8>)> {
+        let block = terrain.get_block(world);
+        let current = self.level(terrain, registry, world);
+
+        if let Some((fluid, level)) = current {
+            for &(reacting, touching, product) in reactions {
+                if fluid == reacting && Face::ALL.iter().any(|face| terrain.get_block(world + face.normal()) == touching) {
+                    return Some((product, None));
+                }
+            }
+            // Sources never drain
+            if registry.fluid(fluid).is_some_and(|properties| level == properties.source_level()) {
+                return None;
+            }
+        } else if block != AIR {
+            return None;
+        }
+
+        // Fluid falling from above arrives at the level of one block away from a source
+        let mut incoming = self
+            .level(terrain, registry, world + IVec3::Y)
+            .and_then(|(fluid, _)| Some((fluid, registry.fluid(fluid)?.flow_distance)));
+        for face in HORIZONTAL {
+            let neighbor = world + face.normal();
+            let Some((fluid, level)) = self.level(terrain, registry, neighbor) else {
+                continue;
+            };
+            let Some(properties) = registry.fluid(fluid) else {
+                continue;
+            };
+            // Fluid that can keep falling does not spread sideways, unless it is a source
+            let below = self.level(terrain, registry, neighbor - IVec3::Y);
+            let falls = terrain.get_block(neighbor - IVec3::Y) == AIR
+                || below.is_some_and(|(below_fluid, below_level)| below_fluid == fluid && below_level < properties.source_level());
+            if level > 1 && (level == properties.source_level() || !falls) && incoming.is_none_or(|(_, best)| level - 1 > best) {
+                incoming = Some((fluid, level - 1));
+            }
+        }
+
+        match incoming {
+            Some((fluid, level)) if current == Some((fluid, level)) => None,
+            Some((fluid, level)) => Some((fluid, Some(level))),
+            None if current.is_some() => Some((AIR, None)),
+            None => None,
+        }
+    }
+
+    // Drop the updates of chunks that were unloaded, and wake the fluids of chunks loaded since the last tick
+    // that may still be flowing: those with a flowing level and those bordering fluid in a neighbouring chunk
+    fn track_loaded_chunks(&mut self, terrain: &VoxelTerrain, registry: &BlockRegistry) {
+        let in_loaded_chunk = |key: &[i32; 3]| terrain.chunks.contains_key(&world_to_chunk(IVec3::from_array(*key)).0);
+        if self.loaded.iter().any(|position| !terrain.chunks.contains_key(position)) {
+            self.loaded.retain(|position| terrain.chunks.contains_key(position));
+            self.dormant.retain(in_loaded_chunk);
+            for due in self.scheduled.values_mut() {
+                due.retain(in_loaded_chunk);
+            }
+            self.scheduled.retain(|_, due| !due.is_empty());
+        }
+
+        for (&position, chunk) in &terrain.chunks {
+            if !self.loaded.insert(position) {
+                continue;
+            }
+            for (index, _) in chunk.fluid_levels() {
+                self.wake(chunk_to_world(position, Chunk::local_from_index(index)));
+            }
+            let last = CHUNK_SIZE - 1;
+            for face in Face::ALL {
+                let axis = face.axis();
+                let (u_axis, v_axis) = ((axis + 1) % 3, (axis + 2) % 3);
+                for u in 0..CHUNK_SIZE {
+                    for v in 0..CHUNK_SIZE {
+                        let mut local = IVec3::ZERO;
+                        local[axis] = if face.is_positive() { last } else { 0 };
+                        local[u_axis] = u;
+                        local[v_axis] = v;
+                        let inside = chunk_to_world(position, local.as_uvec3());
+                        if self.level(terrain, registry, inside + face.normal()).is_some() {
+                            self.schedule(inside, 1);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Advance the simulation by one fixed tick, updating every block that is due
+    pub fn step(&mut self, terrain: &mut VoxelTerrain, registry: &BlockRegistry) {
+        self.track_loaded_chunks(terrain, registry);
+        self.tick += 1;
+        let mut due = self.scheduled.remove(&self.tick).unwrap_or_default();
+        let resumed: Vec<[i32; 3]> = self
+            .dormant
+            .iter()
+            .filter(|key| terrain.is_simulated(world_to_chunk(IVec3::from_array(**key)).0))
+            .copied()
+            .collect();
+        for key in resumed {
+            self.dormant.remove(&key);
+            due.insert(key);
+        }
+        if due.is_empty() {
+            return;
+        }
+
+        let reactions: Vec<(BlockId, BlockId, BlockId)> = self
+            .reactions
+            .iter()
+            .filter_map(|reaction| Some((registry.id(&reaction.fluid)?, registry.id(&reaction.touching)?, registry.id(&reaction.product)?)))
+            .collect();
+
+        // Work out every update from the current state before applying any, so the order of updates does not matter
+        let mut updates = Vec::new();
+        for key in due {
+            let world = IVec3::from_array(key);
+            if !terrain.is_simulated(world_to_chunk(world).0) {
+                self.dormant.insert(key);
+                continue;
+            }
+            if let Some(update) = self.next_state(terrain, registry, &reactions, world) {
+                updates.push((world, update));
+            }
+        }
+
+        for (world, (block, level)) in updates {
+            let previous = terrain.set_block(world, block);
+            terrain.set_fluid_level(world, level);
+            if previous != block {
+                terrain.update_light(world, registry);
+            }
+
+            let delay = registry.fluid(block).or(registry.fluid(previous)).map_or(1, |properties| properties.ticks_per_step);
+            self.schedule(world, delay as u64);
+            for face in Face::ALL {
+                self.schedule(world + face.normal(), delay as u64);
+            }
+        }
+    }
+}
+
+impl VoxelTerrain {
+    // Level of the flowing fluid at a world block coordinate, or None for sources, other blocks and unloaded chunks
+    pub fn fluid_level(&self, world: IVec3) -> Option<u8> {
+        let (chunk, local) = world_to_chunk(world);
+        self.chunks.get(&chunk).and_then(|chunk| chunk.fluid_level(local))
+    }
+
+    // Set the level of a flowing fluid block, or make it a source with None; the level is saved with the chunk
+    pub fn set_fluid_level(&mut self, world: IVec3, level: Option<u8>) {
+        let (position, local) = world_to_chunk(world);
+        let Some(chunk) = self.chunks.get_mut(&position) else {
+            return;
+        };
+        if chunk.fluid_level(local) != level {
+            chunk.set_fluid_level(local, level);
+            self.edited_chunks.insert(position);
+        }
+    }
+}
+
+// System to wake fluids next to edited blocks
+pub fn wake_fluids(mut simulation: ResMut<FluidSimulation>, mut changes: EventReader<BlockChanged>) {
+    for change in changes.read() {
+        simulation.wake(change.position);
+    }
+}
+
+// System to advance the fluid simulation on the fixed timestep
+pub fn tick_fluids(mut simulation: ResMut<FluidSimulation>, mut terrain: ResMut<VoxelTerrain>, registry: Res<BlockRegistry>) {
+    simulation.step(&mut terrain, &registry);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+    use crate::voxel_terrain::{test_registry, BlockEdit, Region, VoxelTerrainPlugin};
+
+    // Headless app with a stone floor, advancing one fixed tick per update
+    fn fluid_app(registry: &BlockRegistry) -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, VoxelTerrainPlugin))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(1.0 / 64.0)))
+            .insert_resource(registry.clone());
+        let stone = registry.id("stone").unwrap();
+        let mut terrain = VoxelTerrain::new(Vec3::ZERO, 1.0);
+        for x in -16..32 {
+            for z in -16..32 {
+                terrain.set_block(IVec3::new(x, 0, z), stone);
+            }
+        }
+        app.insert_resource(terrain);
+        app
+    }
+
+    // Pour a water source onto the floor and let it spread until it settles
+    fn pour_water(registry: &BlockRegistry) -> App {
+        let mut app = fluid_app(registry);
+        app.update();
+        app.world.send_event(BlockEdit::Set {
+            position: IVec3::new(8, 1, 8),
+            block: registry.id("water").unwrap(),
+        });
+        for _ in 0..2000 {
+            app.update();
+        }
+        app
+    }
+
+    #[test]
+    fn water_spreads_deterministically() {
+        let registry = test_registry();
+        let water = registry.id("water").unwrap();
+        let flow_distance = registry.fluid(water).unwrap().flow_distance as i32;
+        let first = pour_water(&registry);
+        assert!(first.world.resource::<FluidSimulation>().is_settled());
+
+        // Water covers a diamond around the source, one level lower for every block it travels
+        let terrain = first.world.resource::<VoxelTerrain>();
+        for x in -16..32 {
+            for z in -16..32 {
+                let world = IVec3::new(x, 1, z);
+                let distance = (x - 8).abs() + (z - 8).abs();
+                if distance <= flow_distance {
+                    assert_eq!(terrain.get_block(world), water, "no water at {}", world);
+                    let level = (distance > 0).then_some((flow_distance + 1 - distance) as u8);
+                    assert_eq!(terrain.fluid_level(world), level, "wrong level at {}", world);
+                } else {
+                    assert_eq!(terrain.get_block(world), AIR, "water at {}", world);
+                }
+            }
+        }
+
+        let second = pour_water(&registry);
+        assert_eq!(terrain.chunks, second.world.resource::<VoxelTerrain>().chunks);
+    }
+
+    #[test]
+    fn flow_resumes_after_saving_and_reloading() {
+        let registry = test_registry();
+        let settled = pour_water(&registry);
+        let expected = &settled.world.resource::<VoxelTerrain>().chunks[&IVec3::ZERO];
+
+        // Save the chunk through a region file while the water is still spreading
+        let mut app = fluid_app(&registry);
+        app.update();
+        app.world.send_event(BlockEdit::Set {
+            position: IVec3::new(8, 1, 8),
+            block: registry.id("water").unwrap(),
+        });
+        for _ in 0..40 {
+            app.update();
+        }
+        let mut region = Region::default();
+        region.insert(IVec3::ZERO, app.world.resource::<VoxelTerrain>().chunks[&IVec3::ZERO].clone());
+        assert!(region.get(IVec3::ZERO).unwrap().fluid_levels().next().is_some());
+        let bytes = region.to_bytes(IVec3::ZERO);
+
+        // Unloading drops the chunk's pending updates, and the reloaded levels pick the flow up again
+        app.world.resource_mut::<VoxelTerrain>().chunks.remove(&IVec3::ZERO);
+        for _ in 0..10 {
+            app.update();
+        }
+        assert!(app.world.resource::<FluidSimulation>().is_settled());
+        let restored = Region::from_bytes(IVec3::ZERO, &bytes).unwrap();
+        app.world.resource_mut::<VoxelTerrain>().insert_chunk(IVec3::ZERO, restored.get(IVec3::ZERO).unwrap().clone());
+        for _ in 0..2000 {
+            app.update();
+        }
+        assert!(app.world.resource::<FluidSimulation>().is_settled());
+        assert_eq!(&app.world.resource::<VoxelTerrain>().chunks[&IVec3::ZERO], expected);
+    }
+}
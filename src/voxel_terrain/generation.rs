@@ -0,0 +1,458 @@
+use bevy::prelude::*;
+
+use super::{
+    chunk_to_world, default_biomes, select_biome, world_to_chunk, Biome, BlockId, BlockRegistry, Chunk, Climate, Noise, Rotation,
+    Structure, StructureSpawn, AIR, CHUNK_SIZE,
+    decoration::{may_reach_chunk, place_in_chunk, PlacedDecoration, MAX_DECORATION_RADIUS},
+};
+
+// Noise layer salts, so every feature samples an independent pattern from the same seed
+const HEIGHT_LAYER: u64 = 1;
+const CAVE_LAYER: u64 = 2;
+const ORE_LAYER: u64 = 3;
+const DECORATION_LAYER: u64 = 6;
+const STRUCTURE_LAYER: u64 = 8;
+
+// Settings for one kind of ore vein embedded in stone
+#[derive(Debug, Clone)]
+pub struct OreVein {
+    pub block: String,
+    pub min_height: i32,
+    pub max_height: i32,
+    pub frequency: f32,
+    // Noise value above which stone is replaced; higher values give rarer, thinner veins
+    pub threshold: f32,
+}
+
+impl OreVein {
+    pub fn new(block: &str, min_height: i32, max_height: i32, frequency: f32, threshold: f32) -> Self {
+        OreVein {
+            block: block.to_string(),
+            min_height,
+            max_height,
+            frequency,
+            threshold,
+        }
+    }
+}
+
+// Tunable parameters for procedural terrain
+#[derive(Debug, Clone)]
+pub struct GeneratorSettings {
+    // Average surface height in blocks
+    pub base_height: i32,
+    // Scale of the surface's deviation from the base height
+    pub height_amplitude: f32,
+    pub height_frequency: f32,
+    pub height_octaves: u32,
+    // Number of filler blocks between the surface block and stone
+    pub filler_depth: i32,
+    pub cave_frequency: f32,
+    // Noise value above which caves are carved
+    pub cave_threshold: f32,
+    // Caves stay at least this many blocks below the surface
+    pub cave_surface_margin: i32,
+    // Surface and filler blocks used where no biome applies
+    pub surface_block: String,
+    pub filler_block: String,
+    pub stone_block: String,
+    pub ores: Vec<OreVein>,
+    // Frequency of the temperature and humidity maps that choose biomes
+    pub climate_frequency: f32,
+    pub biomes: Vec<Biome>,
+    // Structures stamped onto the surface, at most one per chunk column
+    pub structures: Vec<StructureSpawn>,
+}
+
+impl Default for GeneratorSettings {
+    fn default() -> Self {
+        GeneratorSettings {
+            base_height: 0,
+            height_amplitude: 32.0,
+            height_frequency: 0.01,
+            height_octaves: 4,
+            filler_depth: 3,
+            cave_frequency: 0.05,
+            cave_threshold: 0.3,
+            cave_surface_margin: 4,
+            surface_block: "grass".to_string(),
+            filler_block: "dirt".to_string(),
+            stone_block: "stone".to_string(),
+            ores: vec![
+                OreVein::new("coal_ore", -64, 32, 0.12, 0.5),
+                OreVein::new("iron_ore", -64, 16, 0.14, 0.55),
+                OreVein::new("gold_ore", -64, -8, 0.16, 0.6),
+                OreVein::new("ruby_ore", -64, -16, 0.18, 0.62),
+                OreVein::new("diamond_ore", -64, -24, 0.2, 0.65),
+            ],
+            climate_frequency: 0.004,
+            biomes: default_biomes(),
+            structures: Vec::new(),
+        }
+    }
+}
+
+// Look up a block id by name, falling back to air when the registry does not define it
+fn resolve(registry: &BlockRegistry, name: &str) -> BlockId {
+    registry.id(name).unwrap_or_else(|| {
+        warn!("Block registry has no '{}' block, terrain generation will use air instead", name);
+        AIR
+    })
+}
+
+// Generation inputs shared by every block in a world column
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Column {
+    pub height: i32,
+    // Index into the generator's biome table
+    pub biome: Option<usize>,
+    pub surface_block: BlockId,
+    pub filler_block: BlockId,
+}
+
+// Seeded terrain generator; the same seed and settings always produce identical chunks
+#[derive(Debug, Clone)]
+pub struct TerrainGenerator {
+    pub seed: u64,
+    pub settings: GeneratorSettings,
+    height_noise: Noise,
+    cave_noise: Noise,
+    decoration_noise: Noise,
+    climate: Climate,
+    surface_block: BlockId,
+    filler_block: BlockId,
+    stone_block: BlockId,
+    ores: Vec<(BlockId, Noise, OreVein)>,
+    // Surface and filler block ids for each biome, in biome table order
+    biome_blocks: Vec<(BlockId, BlockId)>,
+    // Weighted decorations for each biome, in biome table order
+    biome_decorations: Vec<Vec<(PlacedDecoration, u32)>>,
+    structure_noise: Noise,
+    // Index into the structure spawns and the blocks of each structure that can be placed, before rotation
+    structures: Vec<(usize, Vec<(IVec3, BlockId)>)>,
+}
+
+impl TerrainGenerator {
+    pub fn new(seed: u64, settings: GeneratorSettings, registry: &BlockRegistry) -> Self {
+        let noise = Noise::new(seed);
+        let ores = settings
+            .ores
+            .iter()
+            .enumerate()
+            .filter_map(|(index, vein)| {
+                let block = resolve(registry, &vein.block);
+                (block != AIR).then(|| (block, noise.layer(ORE_LAYER).layer(index as u64), vein.clone()))
+            })
+            .collect();
+        let biome_blocks = settings
+            .biomes
+            .iter()
+            .map(|biome| (resolve(registry, &biome.surface_block), resolve(registry, &biome.filler_block)))
+            .collect();
+        let biome_decorations = settings
+            .biomes
+            .iter()
+            .map(|biome| {
+                biome
+                    .decorations
+                    .iter()
+                    .filter_map(|spawn| PlacedDecoration::resolve(&spawn.decoration, registry).map(|placed| (placed, spawn.weight)))
+                    .collect()
+            })
+            .collect();
+        let structures = settings
+            .structures
+            .iter()
+            .enumerate()
+            .filter_map(|(index, spawn)| {
+                let size = spawn.structure.dimensions();
+                if size.x > CHUNK_SIZE || size.z > CHUNK_SIZE {
+                    warn!("Structure '{}' is wider than a chunk, terrain generation will not place it", spawn.structure.name);
+                    return None;
+                }
+                match spawn.structure.local_blocks(registry) {
+                    Ok(blocks) => Some((index, blocks)),
+                    Err(error) => {
+                        warn!("Terrain generation will not place structure '{}': {}", spawn.structure.name, error);
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        TerrainGenerator {
+            seed,
+            height_noise: noise.layer(HEIGHT_LAYER),
+            cave_noise: noise.layer(CAVE_LAYER),
+            decoration_noise: noise.layer(DECORATION_LAYER),
+            structure_noise: noise.layer(STRUCTURE_LAYER),
+            climate: Climate::new(seed, settings.climate_frequency),
+            surface_block: resolve(registry, &settings.surface_block),
+            filler_block: resolve(registry, &settings.filler_block),
+            stone_block: resolve(registry, &settings.stone_block),
+            ores,
+            biome_blocks,
+            biome_decorations,
+            structures,
+            settings,
+        }
+    }
+
+    // Biome of the column at a world x/z coordinate
+    pub fn biome_at(&self, x: i32, z: i32) -> Option<&Biome> {
+        self.biome_index(x, z).map(|index| &self.settings.biomes[index])
+    }
+
+    fn biome_index(&self, x: i32, z: i32) -> Option<usize> {
+        let (temperature, humidity) = self.climate.sample(x, z);
+        select_biome(&self.settings.biomes, temperature, humidity)
+    }
+
+    // Height, biome and surface blocks of the column at a world x/z coordinate
+    pub fn column_at(&self, x: i32, z: i32) -> Column {
+        let biome = self.biome_index(x, z);
+        let (surface_block, filler_block) = biome.map_or((self.surface_block, self.filler_block), |index| self.biome_blocks[index]);
+        Column {
+            height: self.height_at(x, z),
+            biome,
+            surface_block,
+            filler_block,
+        }
+    }
+
+    // Surface height of the column at a world x/z coordinate
+    pub fn height_at(&self, x: i32, z: i32) -> i32 {
+        let position = Vec2::new(x as f32, z as f32) * self.settings.height_frequency;
+        let offset = self.height_noise.fbm2(position, self.settings.height_octaves) * self.settings.height_amplitude;
+        self.settings.base_height + offset.round() as i32
+    }
+
+    // Whether a cave is carved at a world block coordinate
+    pub fn is_cave(&self, world: IVec3, height: i32) -> bool {
+        world.y < height - self.settings.cave_surface_margin
+            && self.cave_noise.fbm3(world.as_vec3() * self.settings.cave_frequency, 2) > self.settings.cave_threshold
+    }
+
+    // Block placed in stone at a world block coordinate, which may be an ore
+    fn stone_at(&self, world: IVec3) -> BlockId {
+        for (block, noise, vein) in &self.ores {
+            if world.y < vein.min_height || world.y > vein.max_height {
+                continue;
+            }
+            if noise.sample3(world.as_vec3() * vein.frequency) > vein.threshold {
+                return *block;
+            }
+        }
+        self.stone_block
+    }
+
+    // Block at a world block coordinate within the given column
+    pub fn block_at(&self, world: IVec3, column: &Column) -> BlockId {
+        if world.y > column.height || self.is_cave(world, column.height) {
+            AIR
+        } else if world.y == column.height {
+            column.surface_block
+        } else if world.y >= column.height - self.settings.filler_depth {
+            column.filler_block
+        } else {
+            self.stone_at(world)
+        }
+    }
+
+    // Decoration growing out of the column at a world x/z coordinate, if any
+    fn decoration_at(&self, x: i32, z: i32, column: &Column) -> Option<PlacedDecoration> {
+        let biome = column.biome?;
+        let decorations = &self.biome_decorations[biome];
+        let seed = IVec3::new(x, 0, z);
+        if self.decoration_noise.random(seed) >= self.settings.biomes[biome].vegetation_density {
+            return None;
+        }
+
+        let total: u32 = decorations.iter().map(|(_, weight)| weight).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut remaining = ((self.decoration_noise.layer(1).random(seed) * total as f32) as u32).min(total - 1);
+        for (decoration, weight) in decorations {
+            if remaining < *weight {
+                return Some(*decoration);
+            }
+            remaining -= weight;
+        }
+        None
+    }
+
+    // Place decorations into a generated chunk, including ones anchored in neighbouring chunks that reach into it
+    pub fn decorate_chunk(&self, position: IVec3, chunk: &mut Chunk) {
+        let origin = chunk_to_world(position, UVec3::ZERO);
+        // Visit anchors in a fixed world order so overlapping decorations resolve the same way in every chunk
+        for x in origin.x - MAX_DECORATION_RADIUS..origin.x + CHUNK_SIZE + MAX_DECORATION_RADIUS {
+            for z in origin.z - MAX_DECORATION_RADIUS..origin.z + CHUNK_SIZE + MAX_DECORATION_RADIUS {
+                let column = self.column_at(x, z);
+                let anchor = IVec3::new(x, column.height + 1, z);
+                if !may_reach_chunk(anchor.y, position.y) {
+                    continue;
+                }
+                if let Some(decoration) = self.decoration_at(x, z, &column) {
+                    place_in_chunk(chunk, position, &decoration, anchor, &self.decoration_noise);
+                }
+            }
+        }
+    }
+
+    // Structure placed in a chunk column, with its rotated box's lowest corner and its rotation.
+    // Each structure fits inside its column so chunks can be generated without looking at their neighbours.
+    pub fn structure_at(&self, chunk_x: i32, chunk_z: i32) -> Option<(&Structure, IVec3, Rotation)> {
+        self.structure_placement(chunk_x, chunk_z)
+            .map(|(index, origin, rotation)| (&self.settings.structures[self.structures[index].0].structure, origin, rotation))
+    }
+
+    // Index into the placeable structures, origin and rotation of the structure in a chunk column
+    fn structure_placement(&self, chunk_x: i32, chunk_z: i32) -> Option<(usize, IVec3, Rotation)> {
+        let seed = IVec3::new(chunk_x, 0, chunk_z);
+        let index = self.structures.iter().position(|&(spawn, _)| {
+            self.structure_noise.layer(spawn as u64).random(seed) < self.settings.structures[spawn].chance
+        })?;
+        let structure = &self.settings.structures[self.structures[index].0].structure;
+
+        let roll = self.structure_noise.hash(seed);
+        let rotation = Rotation::from_quarter_turns((roll & 3) as i32);
+        let size = rotation.rotate_size(structure.dimensions());
+        let offset_x = ((roll >> 8) % (CHUNK_SIZE - size.x + 1) as u64) as i32;
+        let offset_z = ((roll >> 24) % (CHUNK_SIZE - size.z + 1) as u64) as i32;
+        let corner = chunk_to_world(IVec3::new(chunk_x, 0, chunk_z), UVec3::ZERO) + IVec3::new(offset_x, 0, offset_z);
+        // The ground layer sits level with the surface at the middle of the footprint
+        let height = self.height_at(corner.x + size.x / 2, corner.z + size.z / 2);
+        Some((index, IVec3::new(corner.x, height - structure.ground_level, corner.z), rotation))
+    }
+
+    // Stamp the part of its column's structure that lies inside a generated chunk
+    fn place_structure_in_chunk(&self, position: IVec3, chunk: &mut Chunk) {
+        let Some((index, origin, rotation)) = self.structure_placement(position.x, position.z) else {
+            return;
+        };
+        let (spawn, blocks) = &self.structures[index];
+        let size = self.settings.structures[*spawn].structure.dimensions();
+        let bottom = world_to_chunk(origin).0.y;
+        let top = world_to_chunk(origin + IVec3::Y * (size.y - 1)).0.y;
+        if position.y < bottom || position.y > top {
+            return;
+        }
+        for &(local, block) in blocks {
+            let (block_chunk, local_position) = world_to_chunk(origin + rotation.rotate(local, size));
+            if block_chunk == position {
+                chunk.set(local_position, block);
+            }
+        }
+    }
+
+    // Generate the block data for the chunk at a chunk coordinate
+    pub fn generate_chunk(&self, position: IVec3) -> Chunk {
+        let mut chunk = Chunk::default();
+        for x in 0..CHUNK_SIZE as u32 {
+            for z in 0..CHUNK_SIZE as u32 {
+                let origin = chunk_to_world(position, UVec3::new(x, 0, z));
+                let column = self.column_at(origin.x, origin.z);
+                // Skip the column entirely when the chunk lies above the surface
+                if origin.y > column.height {
+                    continue;
+                }
+                for y in 0..CHUNK_SIZE as u32 {
+                    let block = self.block_at(origin + IVec3::new(0, y as i32, 0), &column);
+                    if block != AIR {
+                        chunk.set(UVec3::new(x, y, z), block);
+                    }
+                }
+            }
+        }
+        self.decorate_chunk(position, &mut chunk);
+        self.place_structure_in_chunk(position, &mut chunk);
+        chunk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel_terrain::test_registry;
+
+    // Chunks around the origin from below the caves to above the tallest trees
+    fn sample_chunks() -> impl Iterator<Item = IVec3> {
+        (-2..2).flat_map(|x| (-3..3).flat_map(move |y| (-2..2).map(move |z| IVec3::new(x, y, z))))
+    }
+
+    #[test]
+    fn same_seed_generates_identical_chunks() {
+        let registry = test_registry();
+        let first = TerrainGenerator::new(42, GeneratorSettings::default(), &registry);
+        let second = TerrainGenerator::new(42, GeneratorSettings::default(), &registry);
+        // The second generator works through the chunks in the opposite order
+        let positions: Vec<IVec3> = sample_chunks().collect();
+        let generated: Vec<Chunk> = positions.iter().rev().map(|&position| second.generate_chunk(position)).collect();
+        for (&position, chunk) in positions.iter().zip(generated.iter().rev()) {
+            assert_eq!(&first.generate_chunk(position), chunk, "chunk {} differs", position);
+        }
+    }
+
+    #[test]
+    fn different_seeds_generate_different_terrain() {
+        let registry = test_registry();
+        let first = TerrainGenerator::new(1, GeneratorSettings::default(), &registry);
+        let second = TerrainGenerator::new(2, GeneratorSettings::default(), &registry);
+        assert!(sample_chunks().any(|position| first.generate_chunk(position) != second.generate_chunk(position)));
+    }
+
+    #[test]
+    fn decorations_match_across_chunk_borders() {
+        let registry = test_registry();
+        let generator = TerrainGenerator::new(7, GeneratorSettings::default(), &registry);
+        let size = 3 * CHUNK_SIZE;
+
+        // Grow every decoration into one world-wide map, visiting anchors in the order decorate_chunk does
+        let mut decorated = std::collections::HashMap::new();
+        let mut crossing = 0;
+        for x in -MAX_DECORATION_RADIUS..size + MAX_DECORATION_RADIUS {
+            for z in -MAX_DECORATION_RADIUS..size + MAX_DECORATION_RADIUS {
+                let column = generator.column_at(x, z);
+                let Some(decoration) = generator.decoration_at(x, z, &column) else {
+                    continue;
+                };
+                let anchor = IVec3::new(x, column.height + 1, z);
+                let anchor_chunk = world_to_chunk(anchor).0;
+                decoration.place(anchor, &generator.decoration_noise, |world, block, replace| {
+                    let current = decorated
+                        .get(&world)
+                        .copied()
+                        .unwrap_or_else(|| generator.block_at(world, &generator.column_at(world.x, world.z)));
+                    if replace || current == AIR {
+                        decorated.insert(world, block);
+                    }
+                    if world_to_chunk(world).0 != anchor_chunk {
+                        crossing += 1;
+                    }
+                });
+            }
+        }
+        assert!(crossing > 0, "no decoration crosses a chunk border");
+
+        // Every chunk generated on its own holds exactly its share of the decorations
+        for chunk_x in 0..3 {
+            for chunk_z in 0..3 {
+                for chunk_y in -3..3 {
+                    let position = IVec3::new(chunk_x, chunk_y, chunk_z);
+                    let chunk = generator.generate_chunk(position);
+                    let side = CHUNK_SIZE as u32;
+                    for index in 0..side.pow(3) {
+                        let local = UVec3::new(index % side, index / side % side, index / side.pow(2));
+                        let world = chunk_to_world(position, local);
+                        let expected = decorated
+                            .get(&world)
+                            .copied()
+                            .unwrap_or_else(|| generator.block_at(world, &generator.column_at(world.x, world.z)));
+                        assert_eq!(chunk.get(local), expected, "block at {} differs", world);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,351 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use super::{
+    chunk_to_world, world_to_chunk, BlockChanged, BlockId, BlockRegistry, Chunk, Face, VoxelTerrain, AIR, CHUNK_SIZE, CHUNK_VOLUME,
+};
+
+// Brightest light level; sunlight under open sky and the strongest emitters have this level
+pub const MAX_LIGHT: u8 = 15;
+
+// The two independent kinds of light stored per voxel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LightChannel {
+    // Light from the sky, which travels straight down without fading
+    Sun,
+    // Light from emissive blocks such as lava
+    Block,
+}
+
+impl LightChannel {
+    pub const ALL: [LightChannel; 2] = [LightChannel::Sun, LightChannel::Block];
+}
+
+// Sunlight and block light levels for every voxel of a chunk, packed into one byte per voxel
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkLight {
+    levels: Vec<u8>,
+}
+
+impl Default for ChunkLight {
+    fn default() -> Self {
+        ChunkLight { levels: vec![0; CHUNK_VOLUME] }
+    }
+}
+
+impl ChunkLight {
+    // Read the light level of one channel at a local coordinate
+    pub fn get(&self, channel: LightChannel, local: UVec3) -> u8 {
+        let packed = self.levels[Chunk::index(local)];
+        match channel {
+            LightChannel::Sun => packed >> 4,
+            LightChannel::Block => packed & 0x0F,
+        }
+    }
+
+    // Write the light level of one channel at a local coordinate
+    pub fn set(&mut self, channel: LightChannel, local: UVec3, level: u8) {
+        let packed = &mut self.levels[Chunk::index(local)];
+        *packed = match channel {
+            LightChannel::Sun => (*packed & 0x0F) | (level.min(MAX_LIGHT) << 4),
+            LightChannel::Block => (*packed & 0xF0) | level.min(MAX_LIGHT),
+        };
+    }
+}
+
+// Whether light can pass through a block
+fn passes_light(registry: &BlockRegistry, block: BlockId) -> bool {
+    block == AIR || registry.is_transparent(block)
+}
+
+// Brightness multiplier for a light level, falling off quickly so unlit caves are nearly black
+pub fn light_brightness(level: u8) -> f32 {
+    0.05 + 0.95 * 0.8f32.powi((MAX_LIGHT - level.min(MAX_LIGHT)) as i32)
+}
+
+impl VoxelTerrain {
+    // Light level of one channel at a world block coordinate; unloaded blocks are dark
+    pub fn light(&self, channel: LightChannel, world: IVec3) -> u8 {
+        let (chunk, local) = world_to_chunk(world);
+        self.light.get(&chunk).map_or(0, |light| light.get(channel, local))
+    }
+
+    // Combined light level at a world block coordinate, the brighter of sunlight and block light
+    pub fn light_level(&self, world: IVec3) -> u8 {
+        self.light(LightChannel::Sun, world).max(self.light(LightChannel::Block, world))
+    }
+
+    // Write a light level, flagging the chunks that show it for remeshing when it changes
+    fn set_light(&mut self, channel: LightChannel, world: IVec3, level: u8) {
+        let (chunk, local) = world_to_chunk(world);
+        let Some(light) = self.light.get_mut(&chunk) else {
+            return;
+        };
+        if light.get(channel, local) != level {
+            light.set(channel, local, level);
+            self.mark_dirty(chunk, local);
+        }
+    }
+
+    // Whether a block sits at the top of the loaded terrain under open sky, with nothing loaded above to shade it
+    fn open_to_sky(&self, world: IVec3) -> bool {
+        let (chunk, local) = world_to_chunk(world);
+        local.y == CHUNK_SIZE as u32 - 1 && !self.chunks.contains_key(&(chunk + IVec3::Y)) && self.above_surface(world)
+    }
+
+    // Whether a block lies above the generated surface of its column, so that only sky is above it
+    // while the chunks over it are not loaded. Without a generator everything counts as above the surface.
+    fn above_surface(&self, world: IVec3) -> bool {
+        self.generator
+            .as_ref()
+            .is_none_or(|generator| world.y > generator.column_at(world.x, world.z).height)
+    }
+
+    // Spread light outward from the queued blocks, raising any neighbour that is darker than it should be
+    fn propagate_light(&mut self, channel: LightChannel, mut queue: VecDeque<IVec3>, registry: &BlockRegistry) {
+        while let Some(world) = queue.pop_front() {
+            let level = self.light(channel, world);
+            if level == 0 {
+                continue;
+            }
+            for face in Face::ALL {
+                let neighbor = world + face.normal();
+                // Full sunlight keeps its strength on the way down, everything else fades by one level per block
+                let spread = if channel == LightChannel::Sun && face == Face::NegY && level == MAX_LIGHT {
+                    MAX_LIGHT
+                } else {
+                    level - 1
+                };
+                if spread == 0 || self.light(channel, neighbor) >= spread {
+                    continue;
+                }
+                let (chunk, _) = world_to_chunk(neighbor);
+                if !self.light.contains_key(&chunk) || !passes_light(registry, self.get_block(neighbor)) {
+                    continue;
+                }
+                self.set_light(channel, neighbor, spread);
+                queue.push_back(neighbor);
+            }
+        }
+    }
+
+    // Darken every block that may have been lit by the queued blocks, given with their former levels.
+    // Returns the brighter blocks bordering the darkened area, which must be propagated again to refill it.
+    fn remove_light(&mut self, channel: LightChannel, mut queue: VecDeque<(IVec3, u8)>, registry: &BlockRegistry) -> VecDeque<IVec3> {
+        let mut relight = VecDeque::new();
+        while let Some((world, level)) = queue.pop_front() {
+            for face in Face::ALL {
+                let neighbor = world + face.normal();
+                let neighbor_level = self.light(channel, neighbor);
+                if neighbor_level == 0 {
+                    continue;
+                }
+                let fed_by_sky = channel == LightChannel::Sun && face == Face::NegY && level == MAX_LIGHT;
+                if neighbor_level < level || fed_by_sky {
+                    self.set_light(channel, neighbor, 0);
+                    queue.push_back((neighbor, neighbor_level));
+                    // Emitters keep shining however their surroundings change
+                    let emission = registry.emission(self.get_block(neighbor));
+                    if channel == LightChannel::Block && emission > 0 {
+                        self.set_light(channel, neighbor, emission);
+                        relight.push_back(neighbor);
+                    }
+                } else {
+                    relight.push_back(neighbor);
+                }
+            }
+        }
+        relight
+    }
+
+    // Compute light for a freshly loaded chunk and let it flow into and out of its loaded neighbours
+    pub fn light_chunk(&mut self, position: IVec3, registry: &BlockRegistry) {
+        let Some(chunk) = self.chunks.get(&position) else {
+            return;
+        };
+        self.light.entry(position).or_default();
+
+        let mut sun = VecDeque::new();
+        let mut block = VecDeque::new();
+        let mut emitters = Vec::new();
+        let mut sky = Vec::new();
+        let nothing_above = !self.chunks.contains_key(&(position + IVec3::Y));
+        for (index, &id) in chunk.blocks().iter().enumerate() {
+            let local = Chunk::local_from_index(index);
+            let world = chunk_to_world(position, local);
+            let emission = registry.emission(id);
+            if emission > 0 {
+                emitters.push((world, emission));
+            }
+            if nothing_above && local.y == CHUNK_SIZE as u32 - 1 && passes_light(registry, id) && self.above_surface(world) {
+                sky.push(world);
+            }
+        }
+        for (world, emission) in emitters {
+            self.set_light(LightChannel::Block, world, emission);
+            block.push_back(world);
+        }
+        for world in sky {
+            self.set_light(LightChannel::Sun, world, MAX_LIGHT);
+            sun.push_back(world);
+        }
+
+        // Pull in light from the blocks bordering the chunk on every side
+        let last = CHUNK_SIZE - 1;
+        for face in Face::ALL {
+            let axis = face.axis();
+            let (u_axis, v_axis) = ((axis + 1) % 3, (axis + 2) % 3);
+            for u in 0..CHUNK_SIZE {
+                for v in 0..CHUNK_SIZE {
+                    let mut local = IVec3::ZERO;
+                    local[axis] = if face.is_positive() { last } else { 0 };
+                    local[u_axis] = u;
+                    local[v_axis] = v;
+                    let outside = chunk_to_world(position, local.as_uvec3()) + face.normal();
+                    if self.light(LightChannel::Sun, outside) > 0 {
+                        sun.push_back(outside);
+                    }
+                    if self.light(LightChannel::Block, outside) > 0 {
+                        block.push_back(outside);
+                    }
+                }
+            }
+        }
+        self.propagate_light(LightChannel::Sun, sun, registry);
+        self.propagate_light(LightChannel::Block, block, registry);
+
+        // The chunk below was lit as if open to the sky; take back the sunlight this chunk now blocks
+        let mut shaded = VecDeque::new();
+        if self.light.contains_key(&(position - IVec3::Y)) {
+            for x in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    let bottom = chunk_to_world(position, UVec3::new(x as u32, 0, z as u32));
+                    let below = bottom - IVec3::Y;
+                    if self.light(LightChannel::Sun, below) == MAX_LIGHT && self.light(LightChannel::Sun, bottom) < MAX_LIGHT {
+                        self.set_light(LightChannel::Sun, below, 0);
+                        shaded.push_back((below, MAX_LIGHT));
+                    }
+                }
+            }
+        }
+        if !shaded.is_empty() {
+            let relight = self.remove_light(LightChannel::Sun, shaded, registry);
+            self.propagate_light(LightChannel::Sun, relight, registry);
+        }
+    }
+
+    // Light every chunk loaded since the last call, top down so sunlight rarely has to be taken back
+    pub fn light_pending_chunks(&mut self, registry: &BlockRegistry) {
+        let mut pending: Vec<IVec3> = self.unlit_chunks.drain().collect();
+        pending.sort_by_key(|position| (-position.y, position.x, position.z));
+        for position in pending {
+            self.light_chunk(position, registry);
+        }
+    }
+
+    // Update light around a block whose type changed
+    pub fn update_light(&mut self, world: IVec3, registry: &BlockRegistry) {
+        let (chunk, _) = world_to_chunk(world);
+        if !self.light.contains_key(&chunk) {
+            return;
+        }
+        let block = self.get_block(world);
+        for channel in LightChannel::ALL {
+            let level = self.light(channel, world);
+            self.set_light(channel, world, 0);
+            let mut queue = self.remove_light(channel, VecDeque::from([(world, level)]), registry);
+
+            if passes_light(registry, block) {
+                // Let the surrounding light flow back into the block
+                queue.extend(Face::ALL.map(|face| world + face.normal()));
+                if channel == LightChannel::Sun && self.open_to_sky(world) {
+                    self.set_light(channel, world, MAX_LIGHT);
+                    queue.push_back(world);
+                }
+            }
+            let emission = registry.emission(block);
+            if channel == LightChannel::Block && emission > 0 {
+                self.set_light(channel, world, emission);
+                queue.push_back(world);
+            }
+            self.propagate_light(channel, queue, registry);
+        }
+    }
+}
+
+// System to light newly loaded chunks and relight around edited blocks
+pub fn update_lighting(
+    mut terrain: ResMut<VoxelTerrain>,
+    registry: Res<BlockRegistry>,
+    mut changes: EventReader<BlockChanged>,
+) {
+    if !terrain.unlit_chunks.is_empty() {
+        terrain.light_pending_chunks(&registry);
+    }
+    for change in changes.read() {
+        terrain.update_light(change.position, &registry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel_terrain::test_registry;
+
+    // Terrain with a generator for the seed and the given chunks generated and lit
+    fn generated(registry: &BlockRegistry, seed: u64, chunks: impl IntoIterator<Item = IVec3>) -> VoxelTerrain {
+        let mut terrain = VoxelTerrain::new(Vec3::ZERO, 1.0).with_seed(seed);
+        let generator = terrain.generator(registry);
+        for position in chunks {
+            terrain.insert_chunk(position, generator.generate_chunk(position));
+        }
+        terrain.light_pending_chunks(registry);
+        terrain
+    }
+
+    fn chunk_blocks(position: IVec3) -> impl Iterator<Item = IVec3> {
+        (0..CHUNK_VOLUME).map(move |index| chunk_to_world(position, Chunk::local_from_index(index)))
+    }
+
+    #[test]
+    fn sealed_cave_stays_dark() {
+        let registry = test_registry();
+        // Find an underground chunk whose top layer is cut open by a cave
+        let position = IVec3::new(0, -3, 0);
+        let (seed, mut terrain) = (0..64)
+            .map(|seed| (seed, generated(&registry, seed, [position])))
+            .find(|(_, terrain)| {
+                chunk_blocks(position).any(|world| world.y == (position.y + 1) * CHUNK_SIZE - 1 && terrain.get_block(world) == AIR)
+            })
+            .expect("no seed has a cave reaching the top of the chunk");
+        let generator = terrain.generator(&registry);
+        assert!(chunk_blocks(position).all(|world| world.y < generator.height_at(world.x, world.z)), "seed {} chunk is not underground", seed);
+
+        assert!(chunk_blocks(position).all(|world| terrain.light(LightChannel::Sun, world) == 0));
+        // Digging inside the cave does not let the sky in either
+        let cave = chunk_blocks(position).find(|&world| terrain.get_block(world) == AIR).unwrap();
+        terrain.update_light(cave, &registry);
+        assert!(chunk_blocks(position).all(|world| terrain.light(LightChannel::Sun, world) == 0));
+    }
+
+    #[test]
+    fn sunlight_reaches_down_a_shaft() {
+        let registry = test_registry();
+        let columns = (-1..=1).flat_map(|x| (-1..=1).map(move |z| (x, z)));
+        let chunks = columns.flat_map(|(x, z)| (-3..=2).map(move |y| IVec3::new(x, y, z)));
+        let mut terrain = generated(&registry, 5, chunks);
+        let surface = terrain.generator(&registry).height_at(8, 8);
+
+        // Dig down from the surface through several chunks
+        let bottom = -3 * CHUNK_SIZE + 1;
+        for y in (bottom..=surface).rev() {
+            let world = IVec3::new(8, y, 8);
+            terrain.set_block(world, AIR);
+            terrain.update_light(world, &registry);
+        }
+        for y in bottom..=surface {
+            assert_eq!(terrain.light(LightChannel::Sun, IVec3::new(8, y, 8)), MAX_LIGHT, "sunlight fades at height {}", y);
+        }
+    }
+}
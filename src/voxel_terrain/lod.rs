@@ -0,0 +1,136 @@
+use bevy::prelude::*;
+
+use super::{BlockId, ChunkLoader, Face, VoxelTerrain, AIR, CHUNK_SIZE};
+
+// Coarsest level of detail; chunks at level n are meshed from cells of 2^n blocks along each axis
+pub const MAX_LOD: u8 = 3;
+
+// Number of blocks along each edge of one cell at a level of detail
+pub fn lod_scale(lod: u8) -> i32 {
+    1 << lod.min(MAX_LOD)
+}
+
+// Distances from the chunk loaders at which chunks switch to coarser meshes
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct LodSettings {
+    // Distance from the nearest chunk loader, in chunks, beyond which chunks are meshed at 2×, 4× and 8× downsampling
+    pub distances: [f32; MAX_LOD as usize],
+}
+
+impl Default for LodSettings {
+    fn default() -> Self {
+        LodSettings {
+            distances: [4.0, 8.0, 16.0],
+        }
+    }
+}
+
+impl LodSettings {
+    // Level of detail for a chunk at a distance from the nearest chunk loader, in chunks
+    pub fn lod_at(&self, distance: f32) -> u8 {
+        self.distances.iter().filter(|&&threshold| distance > threshold).count() as u8
+    }
+}
+
+impl VoxelTerrain {
+    // Level of detail a chunk is meshed at; chunks without one are meshed at full resolution
+    pub fn chunk_lod(&self, position: IVec3) -> u8 {
+        self.chunk_lods.get(&position).copied().unwrap_or(0)
+    }
+
+    // Change the level of detail of a chunk, flagging it and its neighbours for remeshing so their borders match
+    pub fn set_chunk_lod(&mut self, position: IVec3, lod: u8) {
+        let lod = lod.min(MAX_LOD);
+        if self.chunk_lod(position) == lod {
+            return;
+        }
+        if lod == 0 {
+            self.chunk_lods.remove(&position);
+        } else {
+            self.chunk_lods.insert(position, lod);
+        }
+        self.dirty_chunks.insert(position);
+        for face in Face::ALL {
+            if self.chunks.contains_key(&(position + face.normal())) {
+                self.dirty_chunks.insert(position + face.normal());
+            }
+        }
+    }
+
+    // Block standing in for a whole cell at a level of detail, given the cell's coordinate in cells.
+    // Cells less than half full are empty so the coarse surface stays close to the real one, and full cells
+    // show the most common block on top of their columns so grassy ground still looks like grass from afar.
+    pub fn lod_cell(&self, cell: IVec3, lod: u8) -> BlockId {
+        let scale = lod_scale(lod);
+        let origin = cell * scale;
+        if scale == 1 {
+            return self.get_block(origin);
+        }
+
+        let mut filled = 0;
+        let mut tops: Vec<(BlockId, u32)> = Vec::new();
+        for x in 0..scale {
+            for z in 0..scale {
+                let mut top = None;
+                for y in 0..scale {
+                    let block = self.get_block(origin + IVec3::new(x, y, z));
+                    if block != AIR {
+                        filled += 1;
+                        top = Some(block);
+                    }
+                }
+                if let Some(top) = top {
+                    match tops.iter_mut().find(|(block, _)| *block == top) {
+                        Some((_, count)) => *count += 1,
+                        None => tops.push((top, 1)),
+                    }
+                }
+            }
+        }
+        if filled * 2 < scale * scale * scale {
+            return AIR;
+        }
+        // Ties go to the lower id so the result does not depend on the scan order
+        tops.into_iter()
+            .max_by_key(|&(block, count)| (count, std::cmp::Reverse(block)))
+            .map_or(AIR, |(block, _)| block)
+    }
+
+    // Brightest light level among the blocks of a cell at a level of detail
+    pub fn lod_light(&self, cell: IVec3, lod: u8) -> u8 {
+        let scale = lod_scale(lod);
+        let origin = cell * scale;
+        let mut level = 0;
+        for x in 0..scale {
+            for y in 0..scale {
+                for z in 0..scale {
+                    level = level.max(self.light_level(origin + IVec3::new(x, y, z)));
+                }
+            }
+        }
+        level
+    }
+}
+
+// System to pick each loaded chunk's level of detail from its distance to the nearest chunk loader,
+// which follows the player wherever the camera looks from
+pub fn update_chunk_lods(
+    mut terrain: ResMut<VoxelTerrain>,
+    settings: Res<LodSettings>,
+    loaders: Query<&Transform, With<ChunkLoader>>,
+) {
+    // Without a loader every chunk keeps the level it has
+    if loaders.is_empty() {
+        return;
+    }
+    let chunk_width = CHUNK_SIZE as f32 * terrain.voxel_size;
+    let positions: Vec<IVec3> = terrain.chunks.keys().copied().collect();
+    for position in positions {
+        let center = (position.as_vec3() + Vec3::splat(0.5)) * chunk_width;
+        let distance = loaders
+            .iter()
+            .map(|loader| loader.translation.distance(center))
+            .fold(f32::INFINITY, f32::min);
+        terrain.set_chunk_lod(position, settings.lod_at(distance / chunk_width));
+    }
+}
@@ -0,0 +1,462 @@
+use std::cmp::Ordering;
+
+use bevy::{
+    prelude::*,
+    render::{
+        mesh::{Indices, MeshVertexAttribute, PrimitiveTopology},
+        render_asset::RenderAssetUsages,
+        render_resource::VertexFormat,
+    },
+};
+
+use super::{light_brightness, lod_scale, BlockId, VoxelTerrain, AIR, CHUNK_SIZE};
+
+// The six faces of a block, one per axis direction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Face {
+    PosX,
+    NegX,
+    PosY,
+    NegY,
+    PosZ,
+    NegZ,
+}
+
+impl Face {
+    pub const ALL: [Face; 6] = [Face::PosX, Face::NegX, Face::PosY, Face::NegY, Face::PosZ, Face::NegZ];
+
+    // Axis index the face points along (0 = x, 1 = y, 2 = z)
+    pub fn axis(self) -> usize {
+        match self {
+            Face::PosX | Face::NegX => 0,
+            Face::PosY | Face::NegY => 1,
+            Face::PosZ | Face::NegZ => 2,
+        }
+    }
+
+    // Whether the face points along the positive direction of its axis
+    pub fn is_positive(self) -> bool {
+        matches!(self, Face::PosX | Face::PosY | Face::PosZ)
+    }
+
+    // Unit offset to the neighbouring block on this side
+    pub fn normal(self) -> IVec3 {
+        match self {
+            Face::PosX => IVec3::X,
+            Face::NegX => IVec3::NEG_X,
+            Face::PosY => IVec3::Y,
+            Face::NegY => IVec3::NEG_Y,
+            Face::PosZ => IVec3::Z,
+            Face::NegZ => IVec3::NEG_Z,
+        }
+    }
+
+    // Look up the face pointing along a unit axis offset
+    pub fn from_normal(normal: IVec3) -> Option<Face> {
+        Face::ALL.into_iter().find(|face| face.normal() == normal)
+    }
+}
+
+// Per-vertex ambient occlusion in 0..1, where 1 is fully unoccluded
+pub const ATTRIBUTE_AMBIENT_OCCLUSION: MeshVertexAttribute =
+    MeshVertexAttribute::new("Vertex_AmbientOcclusion", 988_540_917, VertexFormat::Float32);
+
+// Brightness for each ambient occlusion level, from a corner boxed in by two sides to a fully open one
+const OCCLUSION_CURVE: [f32; 4] = [0.45, 0.65, 0.82, 1.0];
+
+// Options controlling how chunk meshes are built
+#[derive(Debug, Clone, Copy)]
+pub struct MesherSettings {
+    // Merge coplanar faces of the same block type into larger quads
+    pub greedy: bool,
+    // Darken face corners next to solid blocks
+    pub ambient_occlusion: bool,
+}
+
+impl Default for MesherSettings {
+    fn default() -> Self {
+        MesherSettings {
+            greedy: true,
+            ambient_occlusion: true,
+        }
+    }
+}
+
+// Vertex data for a chunk, kept separate from `Mesh` so it can be inspected without a GPU
+#[derive(Debug, Clone, Default)]
+pub struct ChunkMeshData {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    // Position within the quad in blocks, which the chunk shader wraps into the tile
+    pub uvs: Vec<[f32; 2]>,
+    // Corner of the block's tile in the tile sheet, the same for every vertex of a quad
+    pub tiles: Vec<[f32; 2]>,
+    // Baked light and ambient occlusion as a greyscale tint per vertex
+    pub colors: Vec<[f32; 4]>,
+    pub occlusion: Vec<f32>,
+    pub indices: Vec<u32>,
+}
+
+impl ChunkMeshData {
+    // Check whether no faces were emitted
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    // Number of quads emitted
+    pub fn quad_count(&self) -> usize {
+        self.indices.len() / 6
+    }
+
+    // Number of triangles emitted
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    // Append a quad spanning `size.x` blocks along the face's u axis and `size.y` blocks along its v axis.
+    // `occlusion` holds the ambient occlusion level of each corner in the order origin, +u, +u+v, +v.
+    fn push_quad(&mut self, face: Face, origin: Vec3, size: Vec2, tile: Vec2, brightness: f32, occlusion: [u8; 4]) {
+        let (width, height) = (size.x, size.y);
+        let axis = face.axis();
+        let (u_axis, v_axis) = ((axis + 1) % 3, (axis + 2) % 3);
+        let mut u = Vec3::ZERO;
+        u[u_axis] = width;
+        let mut v = Vec3::ZERO;
+        v[v_axis] = height;
+
+        let base = self.positions.len() as u32;
+        let corners = [origin, origin + u, origin + u + v, origin + v];
+        let extents = [(0.0, 0.0), (width, 0.0), (width, height), (0.0, height)];
+        for ((corner, (du, dv)), level) in corners.into_iter().zip(extents).zip(occlusion) {
+            let occlusion = OCCLUSION_CURVE[level as usize];
+            let shade = brightness * occlusion;
+            self.positions.push(corner.to_array());
+            self.normals.push(face.normal().as_vec3().to_array());
+            self.uvs.push(face_uv(face, du, dv, width, height));
+            self.tiles.push(tile.to_array());
+            self.colors.push([shade, shade, shade, 1.0]);
+            self.occlusion.push(occlusion);
+        }
+
+        // Split the quad along the diagonal whose corners are darker, so occlusion is interpolated
+        // the same way on every quad instead of depending on which diagonal the triangles share
+        let triangles = if occlusion[0] + occlusion[2] > occlusion[1] + occlusion[3] {
+            [0, 1, 3, 1, 2, 3]
+        } else {
+            [0, 1, 2, 0, 2, 3]
+        };
+        // The u and v axes always satisfy u x v = +axis, so negative faces need reversed winding
+        for triangle in triangles.chunks(3) {
+            if face.is_positive() {
+                self.indices.extend(triangle.iter().map(|corner| base + corner));
+            } else {
+                self.indices.extend(triangle.iter().rev().map(|corner| base + corner));
+            }
+        }
+    }
+
+    // Convert the vertex data into a renderable mesh
+    pub fn into_mesh(self) -> Mesh {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default());
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, self.positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_1, self.tiles);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, self.colors);
+        mesh.insert_attribute(ATTRIBUTE_AMBIENT_OCCLUSION, self.occlusion);
+        mesh.insert_indices(Indices::U32(self.indices));
+        mesh
+    }
+}
+
+// Texture coordinates for a quad corner, tiled once per block with side textures kept upright
+fn face_uv(face: Face, du: f32, dv: f32, width: f32, height: f32) -> [f32; 2] {
+    match face.axis() {
+        // u runs along y and v along z
+        0 => [dv, width - du],
+        // u runs along z and v along x
+        1 => [dv, du],
+        // u runs along x and v along y
+        _ => [du, height - dv],
+    }
+}
+
+// Decide whether a face of `block` touching `neighbor` is visible
+fn face_visible(block: BlockId, neighbor: BlockId, is_transparent: &impl Fn(BlockId) -> bool) -> bool {
+    neighbor == AIR || (neighbor != block && is_transparent(neighbor))
+}
+
+// A visible face in a slice mask; only faces with equal keys are merged into one quad
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FaceKey {
+    block: BlockId,
+    // Light level of the block in front of the face
+    light: u8,
+    // Ambient occlusion level of each corner
+    occlusion: [u8; 4],
+}
+
+// Ambient occlusion level of a face corner from the blocks beside and diagonal to it, from 0 (darkest) to 3
+fn corner_occlusion(side_a: bool, side_b: bool, corner: bool) -> u8 {
+    if side_a && side_b {
+        0
+    } else {
+        3 - side_a as u8 - side_b as u8 - corner as u8
+    }
+}
+
+// Build mesh data for one chunk, emitting only faces that border air or transparent blocks.
+// Chunks with a coarser level of detail are meshed from downsampled cells, and quads are scaled to match.
+pub fn mesh_chunk(
+    terrain: &VoxelTerrain,
+    position: IVec3,
+    settings: MesherSettings,
+    is_transparent: impl Fn(BlockId) -> bool,
+) -> ChunkMeshData {
+    let mut data = ChunkMeshData::default();
+    if terrain.chunk(position).is_none() {
+        return data;
+    }
+
+    let lod = terrain.chunk_lod(position);
+    let scale = lod_scale(lod);
+    let cells = CHUNK_SIZE / scale;
+    let first_cell = position * cells;
+
+    // Downsample the chunk and a one cell border around it up front, reaching into neighbouring chunks past the edges
+    let padded = cells + 2;
+    let mut grid = Vec::with_capacity((padded * padded * padded) as usize);
+    for y in -1..=cells {
+        for z in -1..=cells {
+            for x in -1..=cells {
+                let local = IVec3::new(x, y, z);
+                let neighbor = position + local.div_euclid(IVec3::splat(cells));
+                let neighbor_lod = if neighbor == position { lod } else { terrain.chunk_lod(neighbor) };
+                // At a border with a coarser chunk, faces are culled against the cells that chunk actually draws.
+                // A coarser chunk treats finer neighbours as air, closing its border with a skirt that covers
+                // exactly where the finer chunk leaves its border open, so no cracks or doubled faces show.
+                grid.push(match neighbor_lod.cmp(&lod) {
+                    Ordering::Equal => terrain.lod_cell(first_cell + local, lod),
+                    Ordering::Greater => {
+                        let block = (first_cell + local) * scale;
+                        terrain.lod_cell(block.div_euclid(IVec3::splat(lod_scale(neighbor_lod))), neighbor_lod)
+                    }
+                    Ordering::Less => AIR,
+                });
+            }
+        }
+    }
+    let block_at = |local: IVec3| {
+        let padded_local = local + IVec3::ONE;
+        grid[(padded_local.x + padded_local.z * padded + padded_local.y * padded * padded) as usize]
+    };
+    let occludes = |local: IVec3| {
+        let block = block_at(local);
+        block != AIR && !is_transparent(block)
+    };
+
+    let size = cells as usize;
+    let mut mask: Vec<Option<FaceKey>> = vec![None; size * size];
+
+    for face in Face::ALL {
+        let axis = face.axis();
+        let (u_axis, v_axis) = ((axis + 1) % 3, (axis + 2) % 3);
+
+        for slice in 0..cells {
+            // Collect the visible faces of this slice into a 2D mask of block ids, light and occlusion
+            for v in 0..cells {
+                for u in 0..cells {
+                    let mut local = IVec3::ZERO;
+                    local[axis] = slice;
+                    local[u_axis] = u;
+                    local[v_axis] = v;
+                    let block = block_at(local);
+                    let front = local + face.normal();
+                    if block == AIR || !face_visible(block, block_at(front), &is_transparent) {
+                        mask[u as usize + v as usize * size] = None;
+                        continue;
+                    }
+
+                    let mut occlusion = [3; 4];
+                    if settings.ambient_occlusion {
+                        let (mut u_step, mut v_step) = (IVec3::ZERO, IVec3::ZERO);
+                        u_step[u_axis] = 1;
+                        v_step[v_axis] = 1;
+                        // Corners in the same order as the quad's vertices: origin, +u, +u+v, +v
+                        let corners = [(-1, -1), (1, -1), (1, 1), (-1, 1)];
+                        for (level, (du, dv)) in occlusion.iter_mut().zip(corners) {
+                            let side_a = front + u_step * du;
+                            let side_b = front + v_step * dv;
+                            *level = corner_occlusion(occludes(side_a), occludes(side_b), occludes(side_a + v_step * dv));
+                        }
+                    }
+                    mask[u as usize + v as usize * size] = Some(FaceKey {
+                        block,
+                        light: terrain.lod_light(first_cell + front, lod),
+                        occlusion,
+                    });
+                }
+            }
+
+            // Turn the mask into quads, growing each one along u then v while the face key matches
+            let plane = if face.is_positive() { slice + 1 } else { slice };
+            for v in 0..size {
+                let mut u = 0;
+                while u < size {
+                    let Some(key) = mask[u + v * size] else {
+                        u += 1;
+                        continue;
+                    };
+
+                    let mut width = 1;
+                    let mut height = 1;
+                    if settings.greedy {
+                        while u + width < size && mask[u + width + v * size] == Some(key) {
+                            width += 1;
+                        }
+                        'grow: while v + height < size {
+                            for du in 0..width {
+                                if mask[u + du + (v + height) * size] != Some(key) {
+                                    break 'grow;
+                                }
+                            }
+                            height += 1;
+                        }
+                    }
+
+                    for dv in 0..height {
+                        for du in 0..width {
+                            mask[u + du + (v + dv) * size] = None;
+                        }
+                    }
+
+                    // Quads are laid out in cells, then scaled back up to blocks
+                    let scale = scale as f32;
+                    let tile = terrain.block_atlas.as_ref().map_or(Vec2::ZERO, |atlas| atlas.tile(key.block, face));
+                    let mut origin = Vec3::ZERO;
+                    origin[axis] = plane as f32 * scale;
+                    origin[u_axis] = u as f32 * scale;
+                    origin[v_axis] = v as f32 * scale;
+                    data.push_quad(
+                        face,
+                        origin,
+                        Vec2::new(width as f32, height as f32) * scale,
+                        tile,
+                        light_brightness(key.light),
+                        key.occlusion,
+                    );
+                    u += width;
+                }
+            }
+        }
+    }
+
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel_terrain::{Chunk, MAX_LOD};
+
+    const STONE: BlockId = 1;
+
+    // Terrain holding a single chunk at the origin with the given blocks set
+    fn terrain_with(blocks: impl IntoIterator<Item = UVec3>) -> VoxelTerrain {
+        let mut chunk = Chunk::default();
+        for local in blocks {
+            chunk.set(local, STONE);
+        }
+        let mut terrain = VoxelTerrain::new(Vec3::ZERO, 1.0);
+        terrain.insert_chunk(IVec3::ZERO, chunk);
+        terrain
+    }
+
+    fn mesh(terrain: &VoxelTerrain, greedy: bool) -> ChunkMeshData {
+        let settings = MesherSettings {
+            greedy,
+            ambient_occlusion: true,
+        };
+        mesh_chunk(terrain, IVec3::ZERO, settings, |_| false)
+    }
+
+    #[test]
+    fn single_block_has_six_faces() {
+        let terrain = terrain_with([UVec3::new(3, 3, 3)]);
+        for greedy in [false, true] {
+            let data = mesh(&terrain, greedy);
+            assert_eq!(data.quad_count(), 6);
+            assert_eq!(data.triangle_count(), 12);
+            assert_eq!(data.positions.len(), 24);
+        }
+    }
+
+    #[test]
+    fn adjacent_blocks_cull_shared_face() {
+        let terrain = terrain_with([UVec3::new(3, 3, 3), UVec3::new(4, 3, 3)]);
+        assert_eq!(mesh(&terrain, false).triangle_count(), 20);
+        // The four long sides merge across both blocks as well
+        assert_eq!(mesh(&terrain, true).triangle_count(), 12);
+    }
+
+    #[test]
+    fn greedy_merges_flat_slab() {
+        let slab = (0..CHUNK_SIZE as u32).flat_map(|x| (0..CHUNK_SIZE as u32).map(move |z| UVec3::new(x, 0, z)));
+        let terrain = terrain_with(slab);
+        let side = CHUNK_SIZE as usize;
+        assert_eq!(mesh(&terrain, false).quad_count(), 2 * side * side + 4 * side);
+        assert_eq!(mesh(&terrain, true).quad_count(), 6);
+        assert_eq!(mesh(&terrain, true).triangle_count(), 12);
+    }
+
+    #[test]
+    fn transparent_neighbours_keep_faces() {
+        const GLASS: BlockId = 2;
+        let mut terrain = terrain_with([UVec3::new(3, 3, 3)]);
+        terrain.chunk_mut(IVec3::ZERO).unwrap().set(UVec3::new(4, 3, 3), GLASS);
+        let data = mesh_chunk(&terrain, IVec3::ZERO, MesherSettings::default(), |block| block == GLASS);
+        // The stone face behind the glass stays, the glass face against the stone does not
+        assert_eq!(data.quad_count(), 11);
+    }
+
+    // Rolling terrain filling the chunk at the origin up to a height that changes every block
+    fn hills() -> VoxelTerrain {
+        let side = CHUNK_SIZE as u32;
+        let columns = (0..side).flat_map(|x| (0..side).map(move |z| (x, z)));
+        terrain_with(columns.flat_map(|(x, z)| (0..2 + (x * 7 + z * 3) % 13).map(move |y| UVec3::new(x, y, z))))
+    }
+
+    #[test]
+    fn coarser_lods_have_fewer_vertices() {
+        let mut terrain = hills();
+        let counts: Vec<usize> = (0..=MAX_LOD)
+            .map(|lod| {
+                terrain.set_chunk_lod(IVec3::ZERO, lod);
+                mesh(&terrain, true).positions.len()
+            })
+            .collect();
+        // Every level is at most as detailed as the one before it, down to a single box once the hills are smoothed away
+        assert!(counts[1] < counts[0], "vertex counts by LOD: {:?}", counts);
+        assert!(counts.windows(2).all(|pair| pair[1] <= pair[0]), "vertex counts by LOD: {:?}", counts);
+        assert_eq!(counts[MAX_LOD as usize], 24);
+    }
+
+    #[test]
+    fn only_coarser_side_closes_lod_border() {
+        // Two solid chunks side by side, the one at +X meshed at half resolution
+        let mut terrain = VoxelTerrain::new(Vec3::ZERO, 1.0);
+        for position in [IVec3::ZERO, IVec3::X] {
+            terrain.insert_chunk(position, Chunk::filled(STONE));
+        }
+        terrain.set_chunk_lod(IVec3::X, 1);
+        let settings = MesherSettings::default();
+        let facing = |data: &ChunkMeshData, normal: [f32; 3]| data.normals.iter().filter(|&&n| n == normal).count();
+
+        // The finer chunk draws nothing against the solid cells of the coarser one
+        let fine = mesh_chunk(&terrain, IVec3::ZERO, settings, |_| false);
+        assert_eq!(facing(&fine, [1.0, 0.0, 0.0]), 0);
+        // The coarser chunk closes its border with a skirt
+        let coarse = mesh_chunk(&terrain, IVec3::X, settings, |_| false);
+        assert!(facing(&coarse, [-1.0, 0.0, 0.0]) > 0);
+    }
+}
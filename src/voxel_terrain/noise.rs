@@ -0,0 +1,145 @@
+use bevy::prelude::*;
+
+// Seeded gradient noise; sampling is a pure function of the seed and position so results are reproducible
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Noise {
+    seed: u64,
+}
+
+// Gradient directions for 3D noise: the midpoints of a cube's edges
+const GRADIENTS_3D: [Vec3; 12] = [
+    Vec3::new(1.0, 1.0, 0.0),
+    Vec3::new(-1.0, 1.0, 0.0),
+    Vec3::new(1.0, -1.0, 0.0),
+    Vec3::new(-1.0, -1.0, 0.0),
+    Vec3::new(1.0, 0.0, 1.0),
+    Vec3::new(-1.0, 0.0, 1.0),
+    Vec3::new(1.0, 0.0, -1.0),
+    Vec3::new(-1.0, 0.0, -1.0),
+    Vec3::new(0.0, 1.0, 1.0),
+    Vec3::new(0.0, -1.0, 1.0),
+    Vec3::new(0.0, 1.0, -1.0),
+    Vec3::new(0.0, -1.0, -1.0),
+];
+
+// Gradient directions for 2D noise
+const GRADIENTS_2D: [Vec2; 8] = [
+    Vec2::new(1.0, 0.0),
+    Vec2::new(-1.0, 0.0),
+    Vec2::new(0.0, 1.0),
+    Vec2::new(0.0, -1.0),
+    Vec2::new(std::f32::consts::FRAC_1_SQRT_2, std::f32::consts::FRAC_1_SQRT_2),
+    Vec2::new(-std::f32::consts::FRAC_1_SQRT_2, std::f32::consts::FRAC_1_SQRT_2),
+    Vec2::new(std::f32::consts::FRAC_1_SQRT_2, -std::f32::consts::FRAC_1_SQRT_2),
+    Vec2::new(-std::f32::consts::FRAC_1_SQRT_2, -std::f32::consts::FRAC_1_SQRT_2),
+];
+
+// Quintic smoothing curve used to blend between lattice points
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+impl Noise {
+    pub fn new(seed: u64) -> Self {
+        Noise { seed }
+    }
+
+    // Derive an independent noise layer from this one, so each generation feature gets its own pattern
+    pub fn layer(&self, salt: u64) -> Self {
+        Noise::new(mix(self.seed ^ mix(salt)))
+    }
+
+    // Hash a lattice point into 64 well-mixed bits
+    pub fn hash(&self, position: IVec3) -> u64 {
+        let mut hash = self.seed;
+        hash = mix(hash ^ (position.x as u32 as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+        hash = mix(hash ^ (position.y as u32 as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F));
+        hash = mix(hash ^ (position.z as u32 as u64).wrapping_mul(0x1656_67B1_9E37_79F9));
+        hash
+    }
+
+    // Uniform random value in 0..1 for a lattice point
+    pub fn random(&self, position: IVec3) -> f32 {
+        (self.hash(position) >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    // 2D gradient noise in roughly -1..1
+    pub fn sample2(&self, position: Vec2) -> f32 {
+        let cell = position.floor();
+        let offset = position - cell;
+        let cell = cell.as_ivec2();
+
+        let corner = |dx: i32, dy: i32| {
+            let gradient = GRADIENTS_2D[(self.hash(IVec3::new(cell.x + dx, cell.y + dy, 0)) % 8) as usize];
+            gradient.dot(offset - Vec2::new(dx as f32, dy as f32))
+        };
+
+        let (u, v) = (fade(offset.x), fade(offset.y));
+        let bottom = corner(0, 0) + u * (corner(1, 0) - corner(0, 0));
+        let top = corner(0, 1) + u * (corner(1, 1) - corner(0, 1));
+        (bottom + v * (top - bottom)) * std::f32::consts::SQRT_2
+    }
+
+    // 3D gradient noise in roughly -1..1
+    pub fn sample3(&self, position: Vec3) -> f32 {
+        let cell = position.floor();
+        let offset = position - cell;
+        let cell = cell.as_ivec3();
+
+        let corner = |dx: i32, dy: i32, dz: i32| {
+            let gradient = GRADIENTS_3D[(self.hash(cell + IVec3::new(dx, dy, dz)) % 12) as usize];
+            gradient.dot(offset - Vec3::new(dx as f32, dy as f32, dz as f32))
+        };
+
+        let (u, v, w) = (fade(offset.x), fade(offset.y), fade(offset.z));
+        let lerp = |a: f32, b: f32, t: f32| a + t * (b - a);
+        let near = lerp(
+            lerp(corner(0, 0, 0), corner(1, 0, 0), u),
+            lerp(corner(0, 1, 0), corner(1, 1, 0), u),
+            v,
+        );
+        let far = lerp(
+            lerp(corner(0, 0, 1), corner(1, 0, 1), u),
+            lerp(corner(0, 1, 1), corner(1, 1, 1), u),
+            v,
+        );
+        lerp(near, far, w)
+    }
+
+    // Layered 2D noise: each octave doubles the frequency and halves the amplitude
+    pub fn fbm2(&self, position: Vec2, octaves: u32) -> f32 {
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+        let mut max_amplitude = 0.0;
+        for octave in 0..octaves {
+            total += self.layer(octave as u64).sample2(position * frequency) * amplitude;
+            max_amplitude += amplitude;
+            amplitude *= 0.5;
+            frequency *= 2.0;
+        }
+        if max_amplitude > 0.0 { total / max_amplitude } else { 0.0 }
+    }
+
+    // Layered 3D noise: each octave doubles the frequency and halves the amplitude
+    pub fn fbm3(&self, position: Vec3, octaves: u32) -> f32 {
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+        let mut max_amplitude = 0.0;
+        for octave in 0..octaves {
+            total += self.layer(octave as u64).sample3(position * frequency) * amplitude;
+            max_amplitude += amplitude;
+            amplitude *= 0.5;
+            frequency *= 2.0;
+        }
+        if max_amplitude > 0.0 { total / max_amplitude } else { 0.0 }
+    }
+}
+
+// SplitMix64 finalizer
+fn mix(mut value: u64) -> u64 {
+    value = (value ^ (value >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    value ^ (value >> 31)
+}
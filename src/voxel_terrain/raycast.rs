@@ -0,0 +1,181 @@
+use bevy::prelude::*;
+
+use super::{BlockId, VoxelTerrain};
+
+// Result of a ray hitting a block
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RaycastHit {
+    // World block coordinate of the block that was hit
+    pub block: IVec3,
+    pub block_id: BlockId,
+    // Normal of the face the ray entered through; zero when the ray starts inside the block
+    pub normal: IVec3,
+    // Distance along the ray in world units
+    pub distance: f32,
+}
+
+impl RaycastHit {
+    // Block coordinate in front of the face that was hit, where a placed block would go
+    pub fn adjacent_block(&self) -> IVec3 {
+        self.block + self.normal
+    }
+}
+
+impl VoxelTerrain {
+    // Walk the voxel grid along a ray (Amanatides & Woo DDA) and return the first block accepted by `is_hit`
+    pub fn raycast(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        max_distance: f32,
+        is_hit: impl Fn(BlockId) -> bool,
+    ) -> Option<RaycastHit> {
+        let direction = direction.normalize_or_zero();
+        if direction == Vec3::ZERO {
+            return None;
+        }
+
+        // Work in block units so every cell is one unit wide
+        let origin = origin / self.voxel_size;
+        let max_distance = max_distance / self.voxel_size;
+        let mut block = origin.floor().as_ivec3();
+        let mut step = IVec3::ZERO;
+        let mut t_max = Vec3::splat(f32::INFINITY);
+        let mut t_delta = Vec3::splat(f32::INFINITY);
+        for axis in 0..3 {
+            if direction[axis] > 0.0 {
+                step[axis] = 1;
+                t_delta[axis] = 1.0 / direction[axis];
+                t_max[axis] = (block[axis] as f32 + 1.0 - origin[axis]) * t_delta[axis];
+            } else if direction[axis] < 0.0 {
+                step[axis] = -1;
+                t_delta[axis] = -1.0 / direction[axis];
+                t_max[axis] = (origin[axis] - block[axis] as f32) * t_delta[axis];
+            }
+        }
+
+        let mut normal = IVec3::ZERO;
+        let mut distance = 0.0;
+        loop {
+            let block_id = self.get_block(block);
+            if is_hit(block_id) {
+                return Some(RaycastHit {
+                    block,
+                    block_id,
+                    normal,
+                    distance: distance * self.voxel_size,
+                });
+            }
+
+            // Step into whichever neighbouring cell the ray reaches first
+            let axis = if t_max.x < t_max.y && t_max.x < t_max.z {
+                0
+            } else if t_max.y < t_max.z {
+                1
+            } else {
+                2
+            };
+            distance = t_max[axis];
+            if distance > max_distance {
+                return None;
+            }
+            block[axis] += step[axis];
+            t_max[axis] += t_delta[axis];
+            normal = IVec3::ZERO;
+            normal[axis] = -step[axis];
+        }
+    }
+
+    // Check whether the straight line between two world positions passes only through blocks `is_clear` accepts
+    pub fn line_of_sight(&self, from: Vec3, to: Vec3, is_clear: impl Fn(BlockId) -> bool) -> bool {
+        let offset = to - from;
+        self.raycast(from, offset, offset.length(), |block| !is_clear(block)).is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel_terrain::AIR;
+
+    const STONE: BlockId = 3;
+
+    fn terrain_with(voxel_size: f32, blocks: &[IVec3]) -> VoxelTerrain {
+        let mut terrain = VoxelTerrain::new(Vec3::ZERO, voxel_size);
+        for &block in blocks {
+            terrain.set_block(block, STONE);
+        }
+        terrain
+    }
+
+    fn solid(block: BlockId) -> bool {
+        block != AIR
+    }
+
+    #[test]
+    fn ray_starting_inside_a_block_hits_it_with_zero_normal() {
+        let terrain = terrain_with(1.0, &[IVec3::ZERO]);
+        let hit = terrain.raycast(Vec3::splat(0.5), Vec3::X, 10.0, solid).unwrap();
+        assert_eq!(hit, RaycastHit { block: IVec3::ZERO, block_id: STONE, normal: IVec3::ZERO, distance: 0.0 });
+    }
+
+    #[test]
+    fn axis_aligned_rays_hit_the_facing_side() {
+        let origin = Vec3::splat(0.5);
+        let cases = [
+            (Vec3::X, IVec3::new(5, 0, 0), IVec3::NEG_X, 4.5),
+            (Vec3::NEG_X, IVec3::new(-3, 0, 0), IVec3::X, 2.5),
+            (Vec3::Y, IVec3::new(0, 2, 0), IVec3::NEG_Y, 1.5),
+            (Vec3::NEG_Y, IVec3::new(0, -1, 0), IVec3::Y, 0.5),
+            (Vec3::Z, IVec3::new(0, 0, 7), IVec3::NEG_Z, 6.5),
+            (Vec3::NEG_Z, IVec3::new(0, 0, -20), IVec3::Z, 19.5),
+        ];
+        for (direction, block, normal, distance) in cases {
+            let terrain = terrain_with(1.0, &[block]);
+            // The direction does not need to be normalized
+            let hit = terrain.raycast(origin, direction * 3.0, 100.0, solid).unwrap();
+            assert_eq!((hit.block, hit.normal), (block, normal), "ray along {}", direction);
+            assert!((hit.distance - distance).abs() < 1e-5, "ray along {} hit at {}", direction, hit.distance);
+            assert_eq!(hit.adjacent_block(), block + normal);
+        }
+    }
+
+    #[test]
+    fn diagonal_ray_walks_every_crossed_cell() {
+        // A wall across x = 3; the ray climbs one block in y for every two in x
+        let terrain = terrain_with(1.0, &[IVec3::new(3, 1, 0)]);
+        let direction = Vec3::new(2.0, 1.0, 0.0);
+        let hit = terrain.raycast(Vec3::new(0.5, 0.1, 0.5), direction, 100.0, solid).unwrap();
+        assert_eq!((hit.block, hit.normal), (IVec3::new(3, 1, 0), IVec3::NEG_X));
+        assert!((hit.distance - 2.5 * direction.length() / 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn rays_stop_at_max_distance() {
+        let terrain = terrain_with(1.0, &[IVec3::new(5, 0, 0)]);
+        assert!(terrain.raycast(Vec3::splat(0.5), Vec3::X, 4.4, solid).is_none());
+        assert!(terrain.raycast(Vec3::splat(0.5), Vec3::X, 4.5, solid).is_some());
+        assert!(terrain.raycast(Vec3::splat(0.5), Vec3::ZERO, 100.0, solid).is_none());
+    }
+
+    #[test]
+    fn distances_are_in_world_units() {
+        let terrain = terrain_with(0.5, &[IVec3::new(4, 0, 0)]);
+        // The middle of block 0 with half-unit voxels
+        let origin = Vec3::splat(0.25);
+        let hit = terrain.raycast(origin, Vec3::X, 10.0, solid).unwrap();
+        assert_eq!((hit.block, hit.normal), (IVec3::new(4, 0, 0), IVec3::NEG_X));
+        assert!((hit.distance - 1.75).abs() < 1e-5);
+        assert!(terrain.raycast(origin, Vec3::X, 1.7, solid).is_none());
+    }
+
+    #[test]
+    fn line_of_sight_is_blocked_only_by_blocks_between_the_ends() {
+        let terrain = terrain_with(1.0, &[IVec3::new(4, 0, 0)]);
+        let from = Vec3::splat(0.5);
+        assert!(!terrain.line_of_sight(from, Vec3::new(8.5, 0.5, 0.5), |block| block == AIR));
+        assert!(terrain.line_of_sight(from, Vec3::new(3.5, 0.5, 0.5), |block| block == AIR));
+        assert!(terrain.line_of_sight(from, Vec3::new(0.5, 0.5, 8.5), |block| block == AIR));
+        assert!(terrain.line_of_sight(from, Vec3::new(8.5, 0.5, 0.5), |_| true));
+    }
+}
@@ -0,0 +1,342 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs,
+    path::PathBuf,
+};
+
+use bevy::prelude::*;
+
+use super::{BlockId, Chunk, CHUNK_VOLUME};
+
+// Number of chunks along each edge of a region; one region file holds up to REGION_SIZE³ chunks
+pub const REGION_SIZE: i32 = 8;
+
+// Bytes every region file starts with
+const REGION_MAGIC: [u8; 4] = *b"VXRG";
+
+// Current region file format version, bumped whenever the layout changes.
+// Version 1 files, written before fluid levels were saved, can still be read.
+pub const REGION_VERSION: u16 = 2;
+
+// Region coordinate of the region containing a chunk coordinate
+pub fn chunk_to_region(chunk: IVec3) -> IVec3 {
+    chunk.div_euclid(IVec3::splat(REGION_SIZE))
+}
+
+// Errors that can occur while reading or writing region files
+#[derive(Debug)]
+pub enum RegionError {
+    Io(std::io::Error),
+    InvalidMagic,
+    UnsupportedVersion(u16),
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for RegionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegionError::Io(error) => write!(f, "could not access region file: {}", error),
+            RegionError::InvalidMagic => write!(f, "file is not a region file"),
+            RegionError::UnsupportedVersion(version) => write!(f, "region file version {} is not supported", version),
+            RegionError::Corrupt(reason) => write!(f, "region file is corrupt: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for RegionError {}
+
+// Chunks belonging to one region, stored together in a single file.
+//
+// Layout, all integers little endian:
+//   magic "VXRG", version u16, chunk count u32, then for each chunk:
+//   region-local x/y/z u8, palette length u16, palette block ids u16,
+//   run count u32, runs of (palette index u16, length u16) in block index order,
+//   flowing fluid count u16, then (block index u16, level u8) for each flowing fluid block
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Region {
+    chunks: HashMap<IVec3, Chunk>,
+}
+
+impl Region {
+    // Get a chunk by its chunk coordinate
+    pub fn get(&self, position: IVec3) -> Option<&Chunk> {
+        self.chunks.get(&position)
+    }
+
+    // Add or replace a chunk by its chunk coordinate
+    pub fn insert(&mut self, position: IVec3, chunk: Chunk) {
+        self.chunks.insert(position, chunk);
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    // Encode every chunk in the region; all chunks must lie in the region at `position`
+    pub fn to_bytes(&self, position: IVec3) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&REGION_MAGIC);
+        bytes.extend_from_slice(&REGION_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(self.chunks.len() as u32).to_le_bytes());
+
+        // Sorted so the same region always encodes to the same bytes
+        let mut chunks: Vec<_> = self.chunks.iter().collect();
+        chunks.sort_by_key(|(chunk, _)| (chunk.x, chunk.y, chunk.z));
+        for (chunk_position, chunk) in chunks {
+            debug_assert_eq!(chunk_to_region(*chunk_position), position);
+            let local = *chunk_position - position * REGION_SIZE;
+            bytes.extend_from_slice(&[local.x as u8, local.y as u8, local.z as u8]);
+            encode_chunk(chunk, &mut bytes);
+        }
+        bytes
+    }
+
+    // Decode a region file for the region at `position`
+    pub fn from_bytes(position: IVec3, bytes: &[u8]) -> Result<Self, RegionError> {
+        let mut reader = ByteReader { bytes };
+        if reader.take(REGION_MAGIC.len())? != REGION_MAGIC {
+            return Err(RegionError::InvalidMagic);
+        }
+        let version = reader.u16()?;
+        if version == 0 || version > REGION_VERSION {
+            return Err(RegionError::UnsupportedVersion(version));
+        }
+
+        let mut region = Region::default();
+        for _ in 0..reader.u32()? {
+            let local = reader.take(3)?;
+            let local = IVec3::new(local[0] as i32, local[1] as i32, local[2] as i32);
+            if local.cmpge(IVec3::splat(REGION_SIZE)).any() {
+                return Err(RegionError::Corrupt("chunk lies outside its region"));
+            }
+            region.insert(position * REGION_SIZE + local, decode_chunk(&mut reader, version)?);
+        }
+        if !reader.bytes.is_empty() {
+            return Err(RegionError::Corrupt("trailing data after the last chunk"));
+        }
+        Ok(region)
+    }
+}
+
+// Append a chunk as a palette of its distinct blocks followed by run-length encoded palette indices
+fn encode_chunk(chunk: &Chunk, bytes: &mut Vec<u8>) {
+    let mut palette: Vec<BlockId> = Vec::new();
+    let mut runs: Vec<(u16, u16)> = Vec::new();
+    for &block in chunk.blocks() {
+        let index = match palette.iter().position(|&entry| entry == block) {
+            Some(index) => index,
+            None => {
+                palette.push(block);
+                palette.len() - 1
+            }
+        } as u16;
+        match runs.last_mut() {
+            Some((run_index, length)) if *run_index == index => *length += 1,
+            _ => runs.push((index, 1)),
+        }
+    }
+
+    bytes.extend_from_slice(&(palette.len() as u16).to_le_bytes());
+    for block in palette {
+        bytes.extend_from_slice(&block.to_le_bytes());
+    }
+    bytes.extend_from_slice(&(runs.len() as u32).to_le_bytes());
+    for (index, length) in runs {
+        bytes.extend_from_slice(&index.to_le_bytes());
+        bytes.extend_from_slice(&length.to_le_bytes());
+    }
+
+    let levels: Vec<(usize, u8)> = chunk.fluid_levels().collect();
+    bytes.extend_from_slice(&(levels.len() as u16).to_le_bytes());
+    for (index, level) in levels {
+        bytes.extend_from_slice(&(index as u16).to_le_bytes());
+        bytes.push(level);
+    }
+}
+
+fn decode_chunk(reader: &mut ByteReader, version: u16) -> Result<Chunk, RegionError> {
+    let palette_len = reader.u16()?;
+    let palette = (0..palette_len).map(|_| reader.u16()).collect::<Result<Vec<BlockId>, _>>()?;
+    let mut blocks = Vec::with_capacity(CHUNK_VOLUME);
+    for _ in 0..reader.u32()? {
+        let block = *palette
+            .get(reader.u16()? as usize)
+            .ok_or(RegionError::Corrupt("palette index out of range"))?;
+        let length = reader.u16()? as usize;
+        if blocks.len() + length > CHUNK_VOLUME {
+            return Err(RegionError::Corrupt("chunk holds too many blocks"));
+        }
+        blocks.resize(blocks.len() + length, block);
+    }
+    let mut chunk = Chunk::from_blocks(blocks).ok_or(RegionError::Corrupt("chunk holds too few blocks"))?;
+
+    if version >= 2 {
+        for _ in 0..reader.u16()? {
+            let index = reader.u16()? as usize;
+            let level = reader.take(1)?[0];
+            if index >= CHUNK_VOLUME {
+                return Err(RegionError::Corrupt("fluid level lies outside its chunk"));
+            }
+            chunk.set_fluid_level(Chunk::local_from_index(index), Some(level));
+        }
+    }
+    Ok(chunk)
+}
+
+// Cursor over the bytes of a region file
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], RegionError> {
+        if self.bytes.len() < count {
+            return Err(RegionError::Corrupt("unexpected end of file"));
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u16(&mut self) -> Result<u16, RegionError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, RegionError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+// Region files in a save directory, cached in memory once read
+#[derive(Debug, Clone)]
+pub struct WorldStorage {
+    pub directory: PathBuf,
+    regions: HashMap<IVec3, Region>,
+}
+
+impl WorldStorage {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        WorldStorage {
+            directory: directory.into(),
+            regions: HashMap::new(),
+        }
+    }
+
+    // Path of the file holding the region at a region coordinate
+    pub fn region_path(&self, region: IVec3) -> PathBuf {
+        self.directory.join(format!("r.{}.{}.{}.region", region.x, region.y, region.z))
+    }
+
+    // Read a region from disk unless it is already cached; a missing file is an empty region
+    fn region(&mut self, region: IVec3) -> Result<&mut Region, RegionError> {
+        if !self.regions.contains_key(&region) {
+            let loaded = match fs::read(self.region_path(region)) {
+                Ok(bytes) => Region::from_bytes(region, &bytes)?,
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => Region::default(),
+                Err(error) => return Err(RegionError::Io(error)),
+            };
+            self.regions.insert(region, loaded);
+        }
+        Ok(self.regions.get_mut(&region).unwrap())
+    }
+
+    // Load a saved chunk, or None if it was never saved
+    pub fn load_chunk(&mut self, position: IVec3) -> Result<Option<Chunk>, RegionError> {
+        Ok(self.region(chunk_to_region(position))?.get(position).cloned())
+    }
+
+    // Save chunks, rewriting every region file they belong to
+    pub fn save_chunks<'a>(&mut self, chunks: impl IntoIterator<Item = (IVec3, &'a Chunk)>) -> Result<(), RegionError> {
+        let mut touched = HashSet::new();
+        for (position, chunk) in chunks {
+            let region = chunk_to_region(position);
+            self.region(region)?.insert(position, chunk.clone());
+            touched.insert(region);
+        }
+        if touched.is_empty() {
+            return Ok(());
+        }
+
+        fs::create_dir_all(&self.directory).map_err(RegionError::Io)?;
+        for region in touched {
+            let bytes = self.regions[&region].to_bytes(region);
+            // Write to a temporary file first so a crash never leaves a half-written region behind
+            let path = self.region_path(region);
+            let temporary = path.with_extension("region.tmp");
+            fs::write(&temporary, bytes).map_err(RegionError::Io)?;
+            fs::rename(&temporary, &path).map_err(RegionError::Io)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Region with a chunk holding many different blocks and a chunk of a single block
+    fn sample_region(position: IVec3) -> Region {
+        let mut mixed = Chunk::default();
+        for index in 0..CHUNK_VOLUME {
+            let local = Chunk::local_from_index(index);
+            if (local.x + local.y * 3 + local.z * 7) % 5 != 0 {
+                mixed.set(local, (index % 11) as BlockId + 1);
+            }
+        }
+        mixed.set_fluid_level(UVec3::new(4, 0, 9), Some(3));
+        mixed.set_fluid_level(UVec3::new(15, 15, 15), Some(7));
+        let uniform = Chunk::from_blocks(vec![3; CHUNK_VOLUME]).unwrap();
+
+        let mut region = Region::default();
+        region.insert(position * REGION_SIZE + IVec3::new(1, 2, 3), mixed);
+        region.insert(position * REGION_SIZE + IVec3::new(REGION_SIZE - 1, 0, 0), uniform);
+        region
+    }
+
+    #[test]
+    fn chunks_round_trip() {
+        let position = IVec3::new(-1, 0, 2);
+        let region = sample_region(position);
+        let bytes = region.to_bytes(position);
+        let decoded = Region::from_bytes(position, &bytes).unwrap();
+        assert_eq!(decoded, region);
+        assert_eq!(decoded.to_bytes(position), bytes);
+        let mixed = decoded.get(position * REGION_SIZE + IVec3::new(1, 2, 3)).unwrap();
+        assert_eq!(mixed.fluid_level(UVec3::new(4, 0, 9)), Some(3));
+    }
+
+    #[test]
+    fn reads_version_one_without_fluid_levels() {
+        let mut region = Region::default();
+        region.insert(IVec3::new(0, 1, 0), Chunk::filled(3));
+        let mut bytes = region.to_bytes(IVec3::ZERO);
+        // Version 1 chunks end after their runs, without a fluid level count
+        bytes[4..6].copy_from_slice(&1u16.to_le_bytes());
+        bytes.truncate(bytes.len() - 2);
+        assert_eq!(Region::from_bytes(IVec3::ZERO, &bytes).unwrap(), region);
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut bytes = sample_region(IVec3::ZERO).to_bytes(IVec3::ZERO);
+        bytes[4..6].copy_from_slice(&(REGION_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            Region::from_bytes(IVec3::ZERO, &bytes),
+            Err(RegionError::UnsupportedVersion(version)) if version == REGION_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let mut bytes = sample_region(IVec3::ZERO).to_bytes(IVec3::ZERO);
+        bytes[0] = b'X';
+        assert!(matches!(Region::from_bytes(IVec3::ZERO, &bytes), Err(RegionError::InvalidMagic)));
+        assert!(matches!(Region::from_bytes(IVec3::ZERO, b"VX"), Err(RegionError::Corrupt(_))));
+    }
+}
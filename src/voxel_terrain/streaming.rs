@@ -0,0 +1,212 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use bevy::{
+    app::AppExit,
+    prelude::*,
+    tasks::{block_on, poll_once, AsyncComputeTaskPool, Task},
+};
+
+use super::{world_to_chunk, BlockRegistry, Chunk, ChunkMeshData, VoxelTerrain};
+
+// Maximum number of chunk generation tasks started in a single frame
+const MAX_GENERATION_TASKS_PER_FRAME: usize = 8;
+
+// Keeps the chunks around an entity loaded; radii are measured in chunks
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkLoader {
+    // Horizontal distance within which chunks are generated and rendered
+    pub view_radius: i32,
+    // Horizontal distance within which chunks are simulated (fluids, falling blocks)
+    pub simulation_radius: i32,
+    // Vertical distance used by both radii
+    pub vertical_radius: i32,
+}
+
+impl Default for ChunkLoader {
+    fn default() -> Self {
+        ChunkLoader {
+            view_radius: 6,
+            simulation_radius: 3,
+            vertical_radius: 3,
+        }
+    }
+}
+
+// Background work for chunks that are being generated or meshed
+#[derive(Resource, Default)]
+pub struct ChunkTasks {
+    generating: HashMap<IVec3, Task<Chunk>>,
+    meshing: HashMap<IVec3, Task<ChunkMeshData>>,
+}
+
+impl ChunkTasks {
+    // Whether no chunk is waiting on a background task
+    pub fn is_idle(&self) -> bool {
+        self.generating.is_empty() && self.meshing.is_empty()
+    }
+
+    // Whether the chunk at a chunk coordinate is being generated
+    pub fn is_generating(&self, position: IVec3) -> bool {
+        self.generating.contains_key(&position)
+    }
+}
+
+// System to load chunks around every chunk loader and unload chunks no loader needs
+pub fn update_chunk_loaders(
+    mut commands: Commands,
+    mut terrain: ResMut<VoxelTerrain>,
+    registry: Res<BlockRegistry>,
+    mut tasks: ResMut<ChunkTasks>,
+    loaders: Query<(&ChunkLoader, &Transform)>,
+) {
+    // Without loaders the terrain keeps whatever was generated up front
+    if loaders.is_empty() {
+        return;
+    }
+
+    let mut wanted = HashSet::new();
+    let mut simulated = HashSet::new();
+    let mut centers = Vec::new();
+    for (loader, transform) in loaders.iter() {
+        let (center, _) = world_to_chunk(terrain.world_to_block(transform.translation));
+        centers.push(center);
+        for dx in -loader.view_radius..=loader.view_radius {
+            for dz in -loader.view_radius..=loader.view_radius {
+                let horizontal = dx * dx + dz * dz;
+                if horizontal > loader.view_radius * loader.view_radius {
+                    continue;
+                }
+                for dy in -loader.vertical_radius..=loader.vertical_radius {
+                    let position = center + IVec3::new(dx, dy, dz);
+                    wanted.insert(position);
+                    if horizontal <= loader.simulation_radius * loader.simulation_radius {
+                        simulated.insert(position);
+                    }
+                }
+            }
+        }
+    }
+    terrain.simulated_chunks = simulated;
+
+    // Unload chunks that left every loader's range and cancel work nobody needs anymore
+    let unloaded: Vec<IVec3> = terrain.chunks.keys().filter(|position| !wanted.contains(*position)).copied().collect();
+    for position in unloaded {
+        terrain.unload_chunk(position, &mut commands);
+        tasks.meshing.remove(&position);
+    }
+    tasks.generating.retain(|position, _| wanted.contains(position));
+
+    // Start generating the missing chunks closest to a loader first
+    let mut missing: Vec<IVec3> = wanted
+        .into_iter()
+        .filter(|position| !terrain.chunks.contains_key(position) && !tasks.generating.contains_key(position))
+        .collect();
+    let distance = |position: &IVec3| centers.iter().map(|center| (*position - *center).length_squared()).min();
+    missing.sort_by_key(|position| (distance(position), position.x, position.y, position.z));
+
+    let generator = terrain.generator(&registry);
+    let pool = AsyncComputeTaskPool::get();
+    let mut started = 0;
+    for position in missing {
+        // Chunks edited before they were unloaded come back as the player left them
+        if terrain.restore_chunk(position) {
+            continue;
+        }
+        if started == MAX_GENERATION_TASKS_PER_FRAME {
+            break;
+        }
+        let generator = generator.clone();
+        tasks.generating.insert(position, pool.spawn(async move { generator.generate_chunk(position) }));
+        started += 1;
+    }
+}
+
+// System to write edited chunks that were just streamed out to the world storage
+pub fn save_unloaded_chunks(mut terrain: ResMut<VoxelTerrain>) {
+    if terrain.storage.is_none() || terrain.unloaded_chunks.is_empty() {
+        return;
+    }
+    if let Err(error) = terrain.save_unloaded_chunks() {
+        error!("Could not save unloaded chunks: {}", error);
+    }
+}
+
+// System to save every edited chunk when the app exits
+pub fn save_terrain_on_exit(mut exit: EventReader<AppExit>, terrain: Option<ResMut<VoxelTerrain>>) {
+    if exit.is_empty() {
+        return;
+    }
+    exit.clear();
+    if let Some(mut terrain) = terrain {
+        if let Err(error) = terrain.save() {
+            error!("Could not save terrain: {}", error);
+        }
+    }
+}
+
+// System to insert chunks whose generation finished in the background
+pub fn poll_generation_tasks(
+    mut terrain: ResMut<VoxelTerrain>,
+    mut tasks: ResMut<ChunkTasks>,
+) {
+    tasks.generating.retain(|&position, task| match block_on(poll_once(task)) {
+        Some(chunk) => {
+            // An edit that reached the chunk while it was generating has already generated it, so keep that copy
+            if !terrain.chunks.contains_key(&position) {
+                terrain.insert_chunk(position, chunk);
+            }
+            false
+        }
+        None => true,
+    });
+}
+
+// System to rebuild the meshes of chunks whose data changed, building them in the background
+pub fn remesh_dirty_chunks(
+    mut commands: Commands,
+    mut terrain: ResMut<VoxelTerrain>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    registry: Res<BlockRegistry>,
+    mut tasks: ResMut<ChunkTasks>,
+    mut shared_registry: Local<Option<Arc<BlockRegistry>>>,
+) {
+    // Meshing tasks share one copy of the registry, taken again only when the registry changes
+    if registry.is_changed() {
+        *shared_registry = None;
+    }
+
+    let mut finished = Vec::new();
+    tasks.meshing.retain(|&position, task| match block_on(poll_once(task)) {
+        Some(mesh_data) => {
+            finished.push((position, mesh_data));
+            false
+        }
+        None => true,
+    });
+    for (position, mesh_data) in finished {
+        terrain.apply_chunk_mesh(position, mesh_data, &mut commands, &mut meshes);
+    }
+
+    if terrain.dirty_chunks.is_empty() {
+        return;
+    }
+
+    let registry = shared_registry.get_or_insert_with(|| Arc::new(registry.clone())).clone();
+    let pool = AsyncComputeTaskPool::get();
+    let dirty: Vec<IVec3> = terrain.dirty_chunks.drain().collect();
+    for position in dirty {
+        if !terrain.chunks.contains_key(&position) {
+            tasks.meshing.remove(&position);
+            terrain.apply_chunk_mesh(position, ChunkMeshData::default(), &mut commands, &mut meshes);
+            continue;
+        }
+        // The task meshes a copy of the chunk and its neighbours so the terrain stays free to change;
+        // replacing an in-flight task drops and cancels it
+        let snapshot = terrain.snapshot(position);
+        let registry = registry.clone();
+        tasks.meshing.insert(position, pool.spawn(async move { snapshot.build_chunk_mesh(position, &registry) }));
+    }
+}
@@ -0,0 +1,235 @@
+use std::{collections::BTreeMap, fmt, fs, path::Path};
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use super::{BlockId, BlockRegistry, VoxelTerrain, AIR};
+
+// Palette character that leaves whatever block is already in the world
+pub const KEEP_BLOCK: char = ' ';
+
+// Palette block name that clears the block, since the registry has no entry for air
+pub const AIR_BLOCK_NAME: &str = "air";
+
+// Quarter turn applied to a structure around the vertical axis, clockwise when seen from above
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize)]
+pub enum Rotation {
+    #[default]
+    None,
+    Clockwise90,
+    Clockwise180,
+    Clockwise270,
+}
+
+impl Rotation {
+    pub const ALL: [Rotation; 4] = [Rotation::None, Rotation::Clockwise90, Rotation::Clockwise180, Rotation::Clockwise270];
+
+    // Rotation by a number of clockwise quarter turns, wrapping around
+    pub fn from_quarter_turns(turns: i32) -> Self {
+        Rotation::ALL[turns.rem_euclid(4) as usize]
+    }
+
+    // Size of a box of the given size after rotating it
+    pub fn rotate_size(self, size: IVec3) -> IVec3 {
+        match self {
+            Rotation::None | Rotation::Clockwise180 => size,
+            Rotation::Clockwise90 | Rotation::Clockwise270 => IVec3::new(size.z, size.y, size.x),
+        }
+    }
+
+    // Where a block at `local` inside a box of the given size ends up once the box is rotated in place
+    pub fn rotate(self, local: IVec3, size: IVec3) -> IVec3 {
+        match self {
+            Rotation::None => local,
+            Rotation::Clockwise90 => IVec3::new(size.z - 1 - local.z, local.y, local.x),
+            Rotation::Clockwise180 => IVec3::new(size.x - 1 - local.x, local.y, size.z - 1 - local.z),
+            Rotation::Clockwise270 => IVec3::new(local.z, local.y, size.x - 1 - local.x),
+        }
+    }
+}
+
+// Something a structure asks the game to place besides blocks
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub enum MarkerKind {
+    // Where an enemy of the named kind spawns
+    Spawner { enemy: String },
+    // A chest to fill with loot
+    Chest,
+}
+
+// A marker at a position inside a structure, before rotation
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct StructureMarker {
+    pub position: (i32, i32, i32),
+    pub kind: MarkerKind,
+}
+
+// A structure the terrain generator may place on the surface of a chunk column
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructureSpawn {
+    pub structure: Structure,
+    // Chance of the structure appearing in each chunk column
+    pub chance: f32,
+}
+
+impl StructureSpawn {
+    pub fn new(structure: Structure, chance: f32) -> Self {
+        StructureSpawn { structure, chance }
+    }
+}
+
+// A hand-authored set piece, loaded from a RON file and stamped into the world
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Structure {
+    pub name: String,
+    // Number of blocks along x, y and z before rotation
+    pub size: (i32, i32, i32),
+    // Block name for each character used in the layers, or `AIR_BLOCK_NAME`; `KEEP_BLOCK` needs no entry
+    pub palette: BTreeMap<char, String>,
+    // Horizontal slices from the bottom up, each a list of rows along z with one character per block along x
+    pub layers: Vec<Vec<String>>,
+    #[serde(default)]
+    pub markers: Vec<StructureMarker>,
+    // Layer that sits level with the ground and replaces the surface block; the layers below it are buried
+    #[serde(default)]
+    pub ground_level: i32,
+}
+
+// Errors that can occur while loading or placing a structure
+#[derive(Debug)]
+pub enum StructureError {
+    Io(std::io::Error),
+    Parse(ron::Error),
+    // The layers do not match the declared size
+    SizeMismatch(&'static str),
+    UnknownCharacter(char),
+    MarkerOutOfBounds(IVec3),
+    UnknownBlock(String),
+}
+
+impl fmt::Display for StructureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StructureError::Io(error) => write!(f, "could not read structure: {}", error),
+            StructureError::Parse(error) => write!(f, "could not parse structure: {}", error),
+            StructureError::SizeMismatch(what) => write!(f, "structure has the wrong number of {}", what),
+            StructureError::UnknownCharacter(character) => write!(f, "structure uses '{}' but its palette does not define it", character),
+            StructureError::MarkerOutOfBounds(position) => write!(f, "structure marker at {} lies outside the structure", position),
+            StructureError::UnknownBlock(name) => write!(f, "block registry has no '{}' block for structures", name),
+        }
+    }
+}
+
+impl std::error::Error for StructureError {}
+
+impl Structure {
+    // Parse a structure from RON text, checking that its layers match its size and palette
+    pub fn from_ron(source: &str) -> Result<Self, StructureError> {
+        let structure: Structure = ron::de::from_str(source).map_err(StructureError::Parse)?;
+        structure.validate()?;
+        Ok(structure)
+    }
+
+    // Load a structure from a RON file on disk
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, StructureError> {
+        let source = fs::read_to_string(path).map_err(StructureError::Io)?;
+        Structure::from_ron(&source)
+    }
+
+    // Size of the structure before rotation
+    pub fn dimensions(&self) -> IVec3 {
+        IVec3::new(self.size.0, self.size.1, self.size.2)
+    }
+
+    // Check that the layers match the declared size, every character is in the palette and markers lie inside
+    pub fn validate(&self) -> Result<(), StructureError> {
+        let size = self.dimensions();
+        if self.layers.len() != size.y as usize {
+            return Err(StructureError::SizeMismatch("layers"));
+        }
+        for layer in &self.layers {
+            if layer.len() != size.z as usize {
+                return Err(StructureError::SizeMismatch("rows"));
+            }
+            for row in layer {
+                if row.chars().count() != size.x as usize {
+                    return Err(StructureError::SizeMismatch("columns"));
+                }
+                if let Some(character) = row.chars().find(|character| *character != KEEP_BLOCK && !self.palette.contains_key(character)) {
+                    return Err(StructureError::UnknownCharacter(character));
+                }
+            }
+        }
+        for marker in &self.markers {
+            let position = IVec3::new(marker.position.0, marker.position.1, marker.position.2);
+            if position.cmplt(IVec3::ZERO).any() || position.cmpge(size).any() {
+                return Err(StructureError::MarkerOutOfBounds(position));
+            }
+        }
+        Ok(())
+    }
+
+    // Blocks the structure places, at their positions inside it before rotation
+    pub fn local_blocks(&self, registry: &BlockRegistry) -> Result<Vec<(IVec3, BlockId)>, StructureError> {
+        let palette = self
+            .palette
+            .iter()
+            .map(|(&character, name)| {
+                let block = match name.as_str() {
+                    AIR_BLOCK_NAME => AIR,
+                    _ => registry.id(name).ok_or_else(|| StructureError::UnknownBlock(name.clone()))?,
+                };
+                Ok((character, block))
+            })
+            .collect::<Result<BTreeMap<char, BlockId>, StructureError>>()?;
+
+        let mut blocks = Vec::new();
+        for (y, layer) in self.layers.iter().enumerate() {
+            for (z, row) in layer.iter().enumerate() {
+                for (x, character) in row.chars().enumerate() {
+                    if let Some(&block) = palette.get(&character) {
+                        blocks.push((IVec3::new(x as i32, y as i32, z as i32), block));
+                    }
+                }
+            }
+        }
+        Ok(blocks)
+    }
+
+    // Blocks the structure places when its rotated box has its lowest corner at `origin`
+    pub fn blocks(&self, registry: &BlockRegistry, origin: IVec3, rotation: Rotation) -> Result<Vec<(IVec3, BlockId)>, StructureError> {
+        let size = self.dimensions();
+        Ok(self
+            .local_blocks(registry)?
+            .into_iter()
+            .map(|(local, block)| (origin + rotation.rotate(local, size), block))
+            .collect())
+    }
+
+    // Markers in world block coordinates when the rotated box has its lowest corner at `origin`
+    pub fn markers(&self, origin: IVec3, rotation: Rotation) -> Vec<(IVec3, &MarkerKind)> {
+        let size = self.dimensions();
+        self.markers
+            .iter()
+            .map(|marker| {
+                let local = IVec3::new(marker.position.0, marker.position.1, marker.position.2);
+                (origin + rotation.rotate(local, size), &marker.kind)
+            })
+            .collect()
+    }
+}
+
+impl VoxelTerrain {
+    // Stamp a structure into the terrain with its rotated box's lowest corner at `origin`, returning its markers
+    pub fn place_structure<'a>(
+        &mut self,
+        structure: &'a Structure,
+        registry: &BlockRegistry,
+        origin: IVec3,
+        rotation: Rotation,
+    ) -> Result<Vec<(IVec3, &'a MarkerKind)>, StructureError> {
+        let blocks = structure.blocks(registry, origin, rotation)?;
+        self.stamp_blocks(blocks, registry);
+        Ok(structure.markers(origin, rotation))
+    }
+}
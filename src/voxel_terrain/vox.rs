@@ -0,0 +1,340 @@
+use std::{collections::BTreeMap, fmt, fs, path::Path};
+
+use bevy::{
+    asset::{io::Reader as AssetReader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    render::{
+        mesh::{Indices, PrimitiveTopology},
+        render_asset::RenderAssetUsages,
+    },
+    utils::BoxedFuture,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{Face, Structure, KEEP_BLOCK};
+
+// Colour index 0 is empty space in MagicaVoxel, so palettes hold 255 usable colours after it
+pub type VoxPalette = [[u8; 4]; 256];
+
+// One model from a .vox file, with its voxels in Bevy's y-up axes.
+// MagicaVoxel uses z-up, so its x/y/z become x/z/y here with depth flipped to keep the model's handedness.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VoxModel {
+    // Number of voxels along x, y and z
+    pub size: IVec3,
+    // Position and colour index of every filled voxel
+    pub voxels: Vec<(IVec3, u8)>,
+}
+
+// Contents of a MagicaVoxel .vox file; scene graph, material and layer chunks are ignored
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VoxFile {
+    // Models in the order they appear in the file
+    pub models: Vec<VoxModel>,
+    // RGBA colour for each colour index; the default MagicaVoxel palette when the file has none
+    pub palette: VoxPalette,
+}
+
+// Errors that can occur while loading or converting a .vox file
+#[derive(Debug)]
+pub enum VoxError {
+    Io(std::io::Error),
+    // The data does not start with the "VOX " magic and a MAIN chunk
+    InvalidHeader,
+    // A chunk runs past the end of the data or its parent
+    Truncated,
+    // A SIZE chunk without the XYZI chunk that should follow it, or the other way round
+    MismatchedModel,
+    // A voxel lies outside its model's size
+    VoxelOutOfBounds(IVec3),
+    MissingModel(usize),
+    // A colour index used by the model has no block assigned to it
+    UnmappedColor(u8),
+}
+
+impl fmt::Display for VoxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VoxError::Io(error) => write!(f, "could not read .vox file: {}", error),
+            VoxError::InvalidHeader => write!(f, "data is not a .vox file"),
+            VoxError::Truncated => write!(f, ".vox file ends in the middle of a chunk"),
+            VoxError::MismatchedModel => write!(f, ".vox file has a SIZE chunk without a matching XYZI chunk"),
+            VoxError::VoxelOutOfBounds(position) => write!(f, ".vox voxel at {} lies outside its model", position),
+            VoxError::MissingModel(index) => write!(f, ".vox file has no model {}", index),
+            VoxError::UnmappedColor(index) => write!(f, ".vox colour index {} has no block assigned", index),
+        }
+    }
+}
+
+impl std::error::Error for VoxError {}
+
+impl From<std::io::Error> for VoxError {
+    fn from(error: std::io::Error) -> Self {
+        VoxError::Io(error)
+    }
+}
+
+// A chunk of a .vox file: its four-character id, its own content and the chunks nested inside it
+struct VoxChunk<'a> {
+    id: &'a [u8],
+    content: &'a [u8],
+    children: &'a [u8],
+}
+
+// Little-endian reader over a chunk's bytes
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], VoxError> {
+        if self.data.len() < count {
+            return Err(VoxError::Truncated);
+        }
+        let (bytes, rest) = self.data.split_at(count);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn i32(&mut self) -> Result<i32, VoxError> {
+        let bytes = self.bytes(4)?;
+        Ok(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn chunk(&mut self) -> Result<VoxChunk<'a>, VoxError> {
+        let id = self.bytes(4)?;
+        let content_size = self.i32()?;
+        let children_size = self.i32()?;
+        if content_size < 0 || children_size < 0 {
+            return Err(VoxError::Truncated);
+        }
+        Ok(VoxChunk {
+            id,
+            content: self.bytes(content_size as usize)?,
+            children: self.bytes(children_size as usize)?,
+        })
+    }
+}
+
+// MagicaVoxel's built-in palette: a 6×6×6 colour cube without black, then ramps of blue, green, red and grey
+pub fn default_vox_palette() -> VoxPalette {
+    const CUBE: [u8; 6] = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+    const RAMP: [u8; 10] = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+    let mut palette = [[0; 4]; 256];
+    let mut colors = Vec::with_capacity(255);
+    for r in CUBE {
+        for g in CUBE {
+            for b in CUBE {
+                colors.push([r, g, b, 0xff]);
+            }
+        }
+    }
+    colors.pop();
+    for channel in [2, 1, 0] {
+        colors.extend(RAMP.iter().map(|&level| {
+            let mut color = [0, 0, 0, 0xff];
+            color[channel] = level;
+            color
+        }));
+    }
+    colors.extend(RAMP.iter().map(|&level| [level, level, level, 0xff]));
+    palette[1..].copy_from_slice(&colors);
+    palette
+}
+
+impl VoxFile {
+    // Parse the bytes of a .vox file
+    pub fn parse(data: &[u8]) -> Result<Self, VoxError> {
+        let mut reader = Reader { data };
+        if reader.bytes(4).ok() != Some(b"VOX ".as_slice()) {
+            return Err(VoxError::InvalidHeader);
+        }
+        let _version = reader.i32()?;
+        let main = reader.chunk()?;
+        if main.id != b"MAIN" {
+            return Err(VoxError::InvalidHeader);
+        }
+
+        let mut models = Vec::new();
+        let mut palette = default_vox_palette();
+        let mut size = None;
+        let mut chunks = Reader { data: main.children };
+        while !chunks.data.is_empty() {
+            let chunk = chunks.chunk()?;
+            let mut content = Reader { data: chunk.content };
+            match chunk.id {
+                b"SIZE" => {
+                    if size.is_some() {
+                        return Err(VoxError::MismatchedModel);
+                    }
+                    size = Some(IVec3::new(content.i32()?, content.i32()?, content.i32()?));
+                }
+                b"XYZI" => {
+                    let size = size.take().ok_or(VoxError::MismatchedModel)?;
+                    models.push(VoxModel::parse_voxels(size, &mut content)?);
+                }
+                b"RGBA" => {
+                    // Colour index i is stored at entry i - 1, and the last entry is unused
+                    for color in palette[1..].iter_mut() {
+                        color.copy_from_slice(content.bytes(4)?);
+                    }
+                }
+                _ => {}
+            }
+        }
+        if size.is_some() {
+            return Err(VoxError::MismatchedModel);
+        }
+        Ok(VoxFile { models, palette })
+    }
+
+    // Load a .vox file from disk
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, VoxError> {
+        let data = fs::read(path).map_err(VoxError::Io)?;
+        VoxFile::parse(&data)
+    }
+
+    fn model(&self, index: usize) -> Result<&VoxModel, VoxError> {
+        self.models.get(index).ok_or(VoxError::MissingModel(index))
+    }
+
+    // Mesh of one model coloured by the file's palette, with `voxel_size` world units per voxel
+    pub fn mesh(&self, index: usize, voxel_size: f32) -> Result<Mesh, VoxError> {
+        Ok(self.model(index)?.to_mesh(&self.palette, voxel_size))
+    }
+
+    // Stampable structure from one model, placing the named block for each colour index it uses
+    pub fn structure(&self, index: usize, name: &str, blocks: &BTreeMap<u8, String>) -> Result<Structure, VoxError> {
+        self.model(index)?.to_structure(name, blocks)
+    }
+}
+
+impl VoxModel {
+    // Read an XYZI chunk's voxels for a model of the given MagicaVoxel size
+    fn parse_voxels(size: IVec3, content: &mut Reader) -> Result<Self, VoxError> {
+        let size = IVec3::new(size.x, size.z, size.y);
+        let count = content.i32()?.max(0) as usize;
+        let mut voxels = Vec::with_capacity(count.min(content.data.len() / 4));
+        for _ in 0..count {
+            let voxel = content.bytes(4)?;
+            let position = IVec3::new(voxel[0] as i32, voxel[2] as i32, size.z - 1 - voxel[1] as i32);
+            if position.cmplt(IVec3::ZERO).any() || position.cmpge(size).any() {
+                return Err(VoxError::VoxelOutOfBounds(position));
+            }
+            voxels.push((position, voxel[3]));
+        }
+        Ok(VoxModel { size, voxels })
+    }
+
+    // Mesh of the model's visible voxel faces coloured by the palette, centred on the model's bounds
+    pub fn to_mesh(&self, palette: &VoxPalette, voxel_size: f32) -> Mesh {
+        let filled: BTreeMap<[i32; 3], u8> = self.voxels.iter().map(|&(position, color)| (position.to_array(), color)).collect();
+        let center = self.size.as_vec3() / 2.0;
+
+        let mut positions = Vec::new();
+        let mut normals = Vec::new();
+        let mut colors = Vec::new();
+        let mut indices = Vec::new();
+        for (&position, &color) in &filled {
+            let position = IVec3::from_array(position);
+            let [r, g, b, a] = palette[color as usize];
+            let color = Color::rgba_u8(r, g, b, a).as_linear_rgba_f32();
+            for face in Face::ALL {
+                if filled.contains_key(&(position + face.normal()).to_array()) {
+                    continue;
+                }
+                // The face's corners walk around its u and v axes so u x v points out of the voxel
+                let axis = face.axis();
+                let (u_axis, v_axis) = ((axis + 1) % 3, (axis + 2) % 3);
+                let mut origin = position.as_vec3();
+                origin[axis] += face.is_positive() as i32 as f32;
+                let (mut u, mut v) = (Vec3::ZERO, Vec3::ZERO);
+                u[u_axis] = 1.0;
+                v[v_axis] = 1.0;
+
+                let base = positions.len() as u32;
+                for corner in [origin, origin + u, origin + u + v, origin + v] {
+                    positions.push(((corner - center) * voxel_size).to_array());
+                    normals.push(face.normal().as_vec3().to_array());
+                    colors.push(color);
+                }
+                let triangles = if face.is_positive() { [0, 1, 2, 0, 2, 3] } else { [0, 2, 1, 0, 3, 2] };
+                indices.extend(triangles.iter().map(|corner| base + corner));
+            }
+        }
+
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default());
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+        mesh.insert_indices(Indices::U32(indices));
+        mesh
+    }
+
+    // Stampable structure placing the named block for each colour index, leaving empty voxels untouched
+    pub fn to_structure(&self, name: &str, blocks: &BTreeMap<u8, String>) -> Result<Structure, VoxError> {
+        // Structures key their palettes by character, so each colour index gets one outside the ASCII range
+        let character = |color: u8| char::from_u32(0x100 + color as u32).unwrap_or(KEEP_BLOCK);
+
+        let mut palette = BTreeMap::new();
+        let mut grid = vec![vec![vec![KEEP_BLOCK; self.size.x as usize]; self.size.z as usize]; self.size.y as usize];
+        for &(position, color) in &self.voxels {
+            let block = blocks.get(&color).ok_or(VoxError::UnmappedColor(color))?;
+            palette.insert(character(color), block.clone());
+            grid[position.y as usize][position.z as usize][position.x as usize] = character(color);
+        }
+        let layers = grid
+            .into_iter()
+            .map(|layer| layer.into_iter().map(|row| row.into_iter().collect()).collect())
+            .collect();
+
+        Ok(Structure {
+            name: name.to_string(),
+            size: (self.size.x, self.size.y, self.size.z),
+            palette,
+            layers,
+            markers: Vec::new(),
+            ground_level: 0,
+        })
+    }
+}
+
+// How a .vox file is turned into a mesh asset
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct VoxLoaderSettings {
+    // World size of one voxel
+    pub voxel_size: f32,
+}
+
+impl Default for VoxLoaderSettings {
+    fn default() -> Self {
+        VoxLoaderSettings { voxel_size: 1.0 }
+    }
+}
+
+// Asset loader turning the first model of a .vox file into a mesh coloured by the file's palette
+#[derive(Default)]
+pub struct VoxLoader;
+
+impl AssetLoader for VoxLoader {
+    type Asset = Mesh;
+    type Settings = VoxLoaderSettings;
+    type Error = VoxError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut AssetReader,
+        settings: &'a VoxLoaderSettings,
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Mesh, VoxError>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            VoxFile::parse(&bytes)?.mesh(0, settings.voxel_size)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["vox"]
+    }
+}